@@ -0,0 +1,115 @@
+use algebra::{prelude::*, FpParameters};
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+use crate::Error;
+
+/// Decomposes elem into num_bits bits in big-endian order, which enforces that elem < 2^num_bits.
+/// num_bits must be smaller than `MODULUS_BITS`, so that the decomposition is unique without `Boolean::enforce_in_field`.
+pub fn to_bits_fixed<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    elem: &FG,
+    num_bits: usize) -> Result<Vec<Boolean>, Error>
+{
+    let max_bits = F::Params::MODULUS_BITS as usize - 1;
+    if num_bits > max_bits
+    {
+        return Err(Error::InvalidBitWidth{bits: num_bits, max: max_bits});
+    }
+    let bit_values = match elem.get_value() {
+        Some(value) => {
+            let repr_bits = value.into_repr().to_bits();
+            repr_bits[(repr_bits.len() - num_bits)..].iter().map(|b| Some(*b)).collect()
+        },
+        None => vec![None; num_bits],
+    };
+
+    let mut bits = Vec::with_capacity(num_bits);
+    for (i, bit_value) in bit_values.into_iter().enumerate()
+    {
+        bits.push(Boolean::alloc(
+            &mut cs.ns(|| format!("alloc bit {:?}", i)),
+            || bit_value.ok_or(SynthesisError::AssignmentMissing))?);
+    }
+
+    enforce_packing(&mut cs.ns(|| "packing"), &bits, elem)?;
+    Ok(bits)
+}
+
+/// Enforces that the big-endian bits pack to elem, i.e. that elem = sum_i bits[n - 1 - i] * 2^i.
+/// This only holds modulo the field's characteristic. Use `Boolean::enforce_in_field` to make the bits unique.
+pub fn enforce_packing<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    bits: &[Boolean],
+    elem: &FG) -> Result<(), SynthesisError>
+{
+    let mut packed = FG::zero(&mut cs.ns(|| "zero"))?;
+    let mut coeff = F::one();
+    for (i, bit) in bits.iter().rev().enumerate()
+    {
+        packed = packed.conditionally_add_constant(&mut cs.ns(|| format!("add bit {:?}", i)), bit, coeff)?;
+        coeff.double_in_place();
+    }
+    packed.enforce_equal(&mut cs.ns(|| "packed equals elem"), elem)
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::{ConstraintSystem, SynthesisError};
+    use algebra::{FpParameters, biginteger::BigInteger256, prelude::*};
+    use crate::algebra::bits::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::{Fr, FrParameters};
+    use crate::Error;
+
+    #[test]
+    fn strict_bits_test() -> Result<(), SynthesisError> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let value = -Fr::one();
+        let elem = FrGadget::alloc(&mut cs.ns(|| "elem"), || Ok(value))?;
+        let bits = elem.to_bits_strict(&mut cs.ns(|| "strict bits"))?;
+
+        let repr_bits = value.into_repr().to_bits();
+        let expected = &repr_bits[(repr_bits.len() - bits.len())..];
+        assert_eq!(expected, &bits.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>()[..]);
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn non_canonical_bits_rejected_test() -> Result<(), SynthesisError> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let elem = FrGadget::alloc(&mut cs.ns(|| "elem"), || Ok(Fr::one()))?;
+
+        // 1 + p packs to 1 in the field, and still fits in MODULUS_BITS bits.
+        let mut non_canonical = FrParameters::MODULUS;
+        non_canonical.add_nocarry(&BigInteger256::from(1));
+        let repr_bits = non_canonical.to_bits();
+        let num_bits = FrParameters::MODULUS_BITS as usize;
+        let mut bits = Vec::new();
+        for (i, b) in repr_bits[(repr_bits.len() - num_bits)..].iter().enumerate() {
+            bits.push(Boolean::alloc(&mut cs.ns(|| format!("bit {:?}", i)), || Ok(*b))?);
+        }
+
+        enforce_packing(&mut cs.ns(|| "packing"), &bits, &elem)?;
+        assert!(cs.is_satisfied());
+        Boolean::enforce_in_field::<_, _, Fr>(&mut cs.ns(|| "in field"), &bits)?;
+        assert!(!cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_bits_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let elem = FrGadget::alloc(&mut cs.ns(|| "elem"), || Ok(Fr::from(11u32)))?;
+        let bits = to_bits_fixed(&mut cs.ns(|| "4 bits"), &elem, 4)?;
+        assert_eq!(bits.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>(), vec![true, false, true, true]);
+        assert!(cs.is_satisfied());
+        // 11 doesn't fit in 3 bits
+        to_bits_fixed(&mut cs.ns(|| "3 bits"), &elem, 3)?;
+        assert!(!cs.is_satisfied());
+        // A decomposition as wide as the modulus would not be unique
+        assert!(to_bits_fixed(&mut cs.ns(|| "too many bits"), &elem, FrParameters::MODULUS_BITS as usize).is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use algebra::prelude::*;
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::prelude::*;
+use crate::Error;
 
 
 #[derive(Copy, Clone)]
@@ -31,14 +32,23 @@ impl<F : PrimeField> Domain<F> {
         result
     }
 
+    // The query position bits must come from a strict bit decomposition,
+    // such as HashChainGadget::squeeze_indices, otherwise the prover can bias which coset is queried.
     pub fn query_position_to_coset<CS: ConstraintSystem<F>, FG>(&self, 
         mut cs: CS,
         query_pos: &[Boolean],
-        coset_dim: u64) -> Result<Vec<FG>, SynthesisError>
+        coset_dim: u64) -> Result<Vec<FG>, Error>
         where FG: FieldGadget<F, F>
     {
         let mut coset_index = query_pos;
-        assert!(query_pos.len() == self.dim as usize || query_pos.len() == (self.dim - coset_dim) as usize);
+        if coset_dim > self.dim
+        {
+            return Err(Error::CosetTooLarge{coset_dim: coset_dim as usize, dim: self.dim as usize});
+        }
+        if query_pos.len() != self.dim as usize && query_pos.len() != (self.dim - coset_dim) as usize
+        {
+            return Err(Error::InvalidLength{expected: self.dim as usize, found: query_pos.len()});
+        }
         if query_pos.len() == self.dim as usize {
             coset_index = &coset_index[0..(coset_index.len() - coset_dim as usize)];
         }
@@ -55,7 +65,7 @@ impl<F : PrimeField> Domain<F> {
         }
 
         // multiply by domain offset
-        first_point_in_coset.mul_by_constant_in_place(&mut cs, &self.offset);
+        first_point_in_coset.mul_by_constant_in_place(&mut cs, &self.offset)?;
 
         // build coset
         coset.push(first_point_in_coset);
@@ -0,0 +1,51 @@
+use algebra::prelude::*;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+
+/// Returns a Boolean which is true if and only if a = b.
+/// This costs three constraints, two for the check and one for the booleanity of the result.
+pub fn is_equal<F: Field, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    a: &FG,
+    b: &FG) -> Result<Boolean, SynthesisError>
+{
+    let diff = a.sub(&mut cs.ns(|| "a - b"), b)?;
+    let is_equal = Boolean::alloc(&mut cs.ns(|| "alloc is equal"),
+        || diff.get_value().map(|d| d.is_zero()).ok_or(SynthesisError::AssignmentMissing))?;
+    // When diff is zero, any value works for its inverse.
+    let diff_inv = FG::alloc(&mut cs.ns(|| "alloc diff inverse"),
+        || diff.get_value().map(|d| d.inverse().unwrap_or(F::zero())).ok_or(SynthesisError::AssignmentMissing))?;
+
+    let zero = FG::zero(&mut cs.ns(|| "zero"))?;
+    let is_equal_elem = zero.conditionally_add_constant(&mut cs.ns(|| "is equal as field element"), &is_equal, F::one())?;
+    let not_equal_elem = is_equal_elem.negate(&mut cs.ns(|| "negate"))?.add_constant(&mut cs.ns(|| "not equal as field element"), &F::one())?;
+    // diff * diff_inv = 1 - is_equal forces is_equal to be false when diff is non-zero,
+    // and diff * is_equal = 0 forces is_equal to be true when diff is zero.
+    diff.mul_equals(&mut cs.ns(|| "diff * inverse"), &diff_inv, &not_equal_elem)?;
+    diff.mul_equals(&mut cs.ns(|| "diff * is equal"), &is_equal_elem, &zero)?;
+    Ok(is_equal)
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::{ConstraintSystem, SynthesisError};
+    use algebra::prelude::*;
+    use crate::algebra::equality::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+
+    #[test]
+    fn is_equal_test() -> Result<(), SynthesisError> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let a = FrGadget::alloc(&mut cs.ns(|| "a"), || Ok(Fr::from(5u32)))?;
+        let b = FrGadget::alloc(&mut cs.ns(|| "b"), || Ok(Fr::from(5u32)))?;
+        let c = FrGadget::alloc(&mut cs.ns(|| "c"), || Ok(Fr::from(6u32)))?;
+        let num_constraints = cs.num_constraints();
+        assert!(is_equal(&mut cs.ns(|| "a = b"), &a, &b)?.get_value().unwrap());
+        assert_eq!(cs.num_constraints() - num_constraints, 3);
+        assert!(!is_equal(&mut cs.ns(|| "a = c"), &a, &c)?.get_value().unwrap());
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+}
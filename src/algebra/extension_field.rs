@@ -0,0 +1,236 @@
+use algebra::Field;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+use crate::Error;
+
+/// Describes the degree k extension F[X] / (X^k - nonresidue).
+/// The caller must pick the nonresidue such that X^k - nonresidue is irreducible over F,
+/// this is not checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionField<F: Field>
+{
+    pub degree: usize,
+    pub nonresidue: F,
+}
+
+/// An element of an ExtensionField, c_0 + c_1 X + ... + c_{k-1} X^{k-1}.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionFieldElement<F: Field>
+{
+    pub coeffs: Vec<F>,
+}
+
+impl<F: Field> ExtensionField<F> {
+    pub fn new(degree: usize, nonresidue: F) -> Result<Self, Error>
+    {
+        if degree == 0
+        {
+            return Err(Error::ZeroParameter("extension degree"));
+        }
+        Ok(ExtensionField{degree, nonresidue})
+    }
+
+    pub fn zero(&self) -> ExtensionFieldElement<F>
+    {
+        ExtensionFieldElement{coeffs: vec![F::zero(); self.degree]}
+    }
+
+    pub fn from_base(&self, elem: F) -> ExtensionFieldElement<F>
+    {
+        let mut res = self.zero();
+        res.coeffs[0] = elem;
+        res
+    }
+
+    pub fn one(&self) -> ExtensionFieldElement<F>
+    {
+        self.from_base(F::one())
+    }
+
+    pub fn add(&self, a: &ExtensionFieldElement<F>, b: &ExtensionFieldElement<F>) -> ExtensionFieldElement<F>
+    {
+        ExtensionFieldElement{coeffs: (0..self.degree).map(|i| a.coeffs[i] + b.coeffs[i]).collect()}
+    }
+
+    pub fn sub(&self, a: &ExtensionFieldElement<F>, b: &ExtensionFieldElement<F>) -> ExtensionFieldElement<F>
+    {
+        ExtensionFieldElement{coeffs: (0..self.degree).map(|i| a.coeffs[i] - b.coeffs[i]).collect()}
+    }
+
+    pub fn mul_by_base(&self, a: &ExtensionFieldElement<F>, b: &F) -> ExtensionFieldElement<F>
+    {
+        ExtensionFieldElement{coeffs: a.coeffs.iter().map(|c| *c * b).collect()}
+    }
+
+    pub fn mul(&self, a: &ExtensionFieldElement<F>, b: &ExtensionFieldElement<F>) -> ExtensionFieldElement<F>
+    {
+        // Schoolbook multiplication, reducing X^{k + i} to nonresidue * X^i as we go.
+        let mut res = self.zero();
+        for i in 0..self.degree
+        {
+            for j in 0..self.degree
+            {
+                let mut term = a.coeffs[i] * b.coeffs[j];
+                if i + j >= self.degree
+                {
+                    term *= self.nonresidue;
+                }
+                res.coeffs[(i + j) % self.degree] += term;
+            }
+        }
+        res
+    }
+}
+
+/// An element of an ExtensionField in-circuit, as its k coefficients.
+#[derive(Clone)]
+pub struct ExtensionFieldGadget<F: Field, FG: FieldGadget<F, F>>
+{
+    pub coeffs: Vec<FG>,
+    pub _phantom: F,
+}
+
+impl<F: Field, FG: FieldGadget<F, F>> ExtensionFieldGadget<F, FG> {
+    pub fn from_coeffs(coeffs: Vec<FG>) -> Self
+    {
+        ExtensionFieldGadget{coeffs, _phantom: F::zero()}
+    }
+
+    pub fn alloc<CS: ConstraintSystem<F>>(
+        mut cs: CS,
+        field: &ExtensionField<F>,
+        value: Option<&ExtensionFieldElement<F>>) -> Result<Self, SynthesisError>
+    {
+        let mut coeffs = Vec::with_capacity(field.degree);
+        for i in 0..field.degree
+        {
+            coeffs.push(FG::alloc(&mut cs.ns(|| format!("coeff {:?}", i)),
+                || value.map(|v| v.coeffs[i]).ok_or(SynthesisError::AssignmentMissing))?);
+        }
+        Ok(Self::from_coeffs(coeffs))
+    }
+
+    pub fn get_value(&self) -> Option<ExtensionFieldElement<F>>
+    {
+        let mut coeffs = Vec::with_capacity(self.coeffs.len());
+        for c in self.coeffs.iter()
+        {
+            coeffs.push(c.get_value()?);
+        }
+        Some(ExtensionFieldElement{coeffs})
+    }
+
+    pub fn add<CS: ConstraintSystem<F>>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError>
+    {
+        let mut coeffs = Vec::with_capacity(self.coeffs.len());
+        for i in 0..self.coeffs.len()
+        {
+            coeffs.push(self.coeffs[i].add(&mut cs.ns(|| format!("coeff {:?}", i)), &other.coeffs[i])?);
+        }
+        Ok(Self::from_coeffs(coeffs))
+    }
+
+    pub fn sub<CS: ConstraintSystem<F>>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError>
+    {
+        let mut coeffs = Vec::with_capacity(self.coeffs.len());
+        for i in 0..self.coeffs.len()
+        {
+            coeffs.push(self.coeffs[i].sub(&mut cs.ns(|| format!("coeff {:?}", i)), &other.coeffs[i])?);
+        }
+        Ok(Self::from_coeffs(coeffs))
+    }
+
+    /// Multiplies by an element of the base field, which costs one constraint per coefficient.
+    pub fn mul_by_base<CS: ConstraintSystem<F>>(&self, mut cs: CS, other: &FG) -> Result<Self, SynthesisError>
+    {
+        let mut coeffs = Vec::with_capacity(self.coeffs.len());
+        for i in 0..self.coeffs.len()
+        {
+            coeffs.push(self.coeffs[i].mul(&mut cs.ns(|| format!("coeff {:?}", i)), other)?);
+        }
+        Ok(Self::from_coeffs(coeffs))
+    }
+
+    /// Schoolbook multiplication, this costs k^2 constraints.
+    pub fn mul<CS: ConstraintSystem<F>>(&self, mut cs: CS, other: &Self, field: &ExtensionField<F>) -> Result<Self, SynthesisError>
+    {
+        let mut coeffs = Vec::with_capacity(field.degree);
+        for i in 0..field.degree
+        {
+            coeffs.push(FG::zero(&mut cs.ns(|| format!("zero {:?}", i)))?);
+        }
+        for i in 0..field.degree
+        {
+            for j in 0..field.degree
+            {
+                let mut cs_ij = cs.ns(|| format!("term {:?} {:?}", i, j));
+                let mut term = self.coeffs[i].mul(&mut cs_ij.ns(|| "mul"), &other.coeffs[j])?;
+                if i + j >= field.degree
+                {
+                    term.mul_by_constant_in_place(&mut cs_ij.ns(|| "reduce"), &field.nonresidue)?;
+                }
+                coeffs[(i + j) % field.degree].add_in_place(&mut cs_ij.ns(|| "add"), &term)?;
+            }
+        }
+        Ok(Self::from_coeffs(coeffs))
+    }
+
+    pub fn enforce_equal<CS: ConstraintSystem<F>>(&self, mut cs: CS, other: &Self) -> Result<(), SynthesisError>
+    {
+        for i in 0..self.coeffs.len()
+        {
+            self.coeffs[i].enforce_equal(&mut cs.ns(|| format!("coeff {:?}", i)), &other.coeffs[i])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::algebra::extension_field::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    fn elem(coeffs: &[u32]) -> ExtensionFieldElement<Fr>
+    {
+        ExtensionFieldElement{coeffs: coeffs.iter().map(|c| Fr::from(*c)).collect()}
+    }
+
+    #[test]
+    fn extension_field_mul_test() -> Result<(), Error> {
+        assert!(ExtensionField::new(0, Fr::from(5u32)).is_err());
+        let field = ExtensionField::new(3, Fr::from(5u32))?;
+        // X * X^2 = X^3 = 5
+        assert_eq!(field.mul(&elem(&[0, 1, 0]), &elem(&[0, 0, 1])), field.from_base(Fr::from(5u32)));
+        // (1 + X)(2 + X^2) = 2 + 2X + X^2 + X^3 = 7 + 2X + X^2
+        assert_eq!(field.mul(&elem(&[1, 1, 0]), &elem(&[2, 0, 1])), elem(&[7, 2, 1]));
+        assert_eq!(field.mul(&elem(&[4, 9, 2]), &field.one()), elem(&[4, 9, 2]));
+        Ok(())
+    }
+
+    #[test]
+    fn extension_field_gadget_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let field = ExtensionField::new(3, Fr::from(5u32))?;
+        let a = elem(&[4, 9, 2]);
+        let b = elem(&[1, 7, 3]);
+        let a_gadget = ExtensionFieldGadget::<Fr, FrGadget>::alloc(&mut cs.ns(|| "a"), &field, Some(&a))?;
+        let b_gadget = ExtensionFieldGadget::<Fr, FrGadget>::alloc(&mut cs.ns(|| "b"), &field, Some(&b))?;
+
+        let prod = a_gadget.mul(&mut cs.ns(|| "a * b"), &b_gadget, &field)?;
+        assert_eq!(prod.get_value().unwrap(), field.mul(&a, &b));
+        let sum = a_gadget.add(&mut cs.ns(|| "a + b"), &b_gadget)?;
+        assert_eq!(sum.get_value().unwrap(), field.add(&a, &b));
+        let diff = a_gadget.sub(&mut cs.ns(|| "a - b"), &b_gadget)?;
+        assert_eq!(diff.get_value().unwrap(), field.sub(&a, &b));
+
+        let expected = ExtensionFieldGadget::<Fr, FrGadget>::alloc(&mut cs.ns(|| "expected"), &field, Some(&field.mul(&a, &b)))?;
+        prod.enforce_equal(&mut cs.ns(|| "check product"), &expected)?;
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+}
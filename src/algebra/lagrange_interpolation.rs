@@ -6,6 +6,7 @@ use crate::algebra::vanishing_polynomial::VanishingPolynomial;
 use algebra::fields::batch_inversion;
 use num_traits::Zero;
 use std::convert::{AsRef, From};
+use crate::Error;
 
 /// Struct describing Lagrange interpolation for a multiplicative coset I,
 /// with |I| a power of 2.
@@ -41,10 +42,13 @@ impl<F: PrimeField> LagrangeInterpolator<F> {
         domain_offset: F,
         domain_generator: F, 
         domain_dim: u64, 
-        poly_evaluations: Vec<F>) -> Self
+        poly_evaluations: Vec<F>) -> Result<Self, Error>
     {
         let domain_order = 1 << domain_dim;
-        assert_eq!(poly_evaluations.len(), domain_order);
+        if poly_evaluations.len() != domain_order
+        {
+            return Err(Error::InvalidLength{expected: domain_order, found: poly_evaluations.len()});
+        }
         let mut cur_elem = domain_offset;
         let mut all_domain_elems = vec![domain_offset];
         let mut v_inv_elems : Vec<F> = Vec::new();
@@ -63,7 +67,7 @@ impl<F: PrimeField> LagrangeInterpolator<F> {
         ///   v_inv[0] = m*h^{m-1}
         ///   v_inv[i] = g^{-1} * v_inv[i-1]
         /// TODO: Include proof of the above two points
-        let g_inv = domain_generator.inverse().unwrap();
+        let g_inv = domain_generator.inverse().ok_or(Error::NotInvertible)?;
         let m = F::from((1 << domain_dim) as u64);
         let mut v_inv_i = m * domain_offset.pow([(domain_order - 1) as u64]);
         for _ in 0..domain_order
@@ -82,10 +86,10 @@ impl<F: PrimeField> LagrangeInterpolator<F> {
             domain_vp: vp,
             poly_evaluations,
         };
-        lagrange_interpolation
+        Ok(lagrange_interpolation)
     }
 
-    fn compute_lagrange_coefficients(&self, interpolation_point: F) -> Vec<F>
+    fn compute_lagrange_coefficients(&self, interpolation_point: F) -> Result<Vec<F>, Error>
     {
         /*
         * Let t be the interpolation point, H be the multiplicative coset, with elements of the form h*g^i.
@@ -98,7 +102,8 @@ impl<F: PrimeField> LagrangeInterpolator<F> {
         TODO: explain deriviation more step by step
         */
         // TODO: Implement batch_inverse & mul like libiop for better efficiency
-        let vp_t_inv = self.domain_vp.evaluate(&interpolation_point).inverse().unwrap();
+        // Z_H(t) is only zero when t is in the interpolation domain
+        let vp_t_inv = self.domain_vp.evaluate(&interpolation_point).inverse().ok_or(Error::NotInvertible)?;
         let mut inverted_lagrange_coeffs : Vec<F> = Vec::with_capacity(self.all_domain_elems.len());
         for i in 0..self.domain_order
         {
@@ -108,18 +113,18 @@ impl<F: PrimeField> LagrangeInterpolator<F> {
         }
         let lagrange_coeffs = inverted_lagrange_coeffs.as_mut_slice();
         batch_inversion::<F>(lagrange_coeffs);
-        lagrange_coeffs.iter().cloned().collect()
+        Ok(lagrange_coeffs.iter().cloned().collect())
     }
 
-    pub fn interpolate(&self, interpolation_point: F) -> F
+    pub fn interpolate(&self, interpolation_point: F) -> Result<F, Error>
     {
-        let lagrange_coeffs = self.compute_lagrange_coefficients(interpolation_point);
+        let lagrange_coeffs = self.compute_lagrange_coefficients(interpolation_point)?;
         let mut interpolation = F::zero();
         for i in 0..self.domain_order
         {
             interpolation += (lagrange_coeffs[i] * self.poly_evaluations[i]);
         }
-        interpolation
+        Ok(interpolation)
     }
 }
 
@@ -128,12 +133,17 @@ impl<F: PrimeField> LagrangeInterpolationGadget<F> {
         domain_offset: F, 
         domain_generator: F, 
         domain_dim: u64, 
-        poly_evaluations: Vec<FpGadget<F>>) -> Self
+        poly_evaluations: Vec<FpGadget<F>>) -> Result<Self, Error>
     {
+        let domain_order = 1 << domain_dim;
+        if poly_evaluations.len() != domain_order
+        {
+            return Err(Error::InvalidLength{expected: domain_order, found: poly_evaluations.len()});
+        }
         let mut poly_evaluations_F : Vec<F> = Vec::new();
-        for i in 0..(1 << domain_dim)
+        for i in 0..domain_order
         {
-            poly_evaluations_F.push(poly_evaluations[i].get_value().unwrap());
+            poly_evaluations_F.push(poly_evaluations[i].get_value().ok_or(Error::AssignmentMissing)?);
         }
 
         let lagrange_interpolator : LagrangeInterpolator<F> = LagrangeInterpolator::new(
@@ -141,64 +151,65 @@ impl<F: PrimeField> LagrangeInterpolationGadget<F> {
             domain_generator,
             domain_dim,
             poly_evaluations_F,
-        );
+        )?;
 
         let lagrange_interpolation_gadget = LagrangeInterpolationGadget{
             lagrange_interpolator,
             vp_t : None,
             poly_evaluations,
         };
-        lagrange_interpolation_gadget
+        Ok(lagrange_interpolation_gadget)
     }
 
     fn compute_lagrange_coefficients_constraints<CS: ConstraintSystem<F>>(&mut self,
         mut cs: CS,
         interpolation_point: &FpGadget<F>,
-        ) -> Vec<FpGadget<F>>
+        ) -> Result<Vec<FpGadget<F>>, Error>
     {
         let t = interpolation_point;
-        let lagrange_coeffs = self.lagrange_interpolator.compute_lagrange_coefficients(t.get_value().unwrap());
+        let t_value = t.get_value().ok_or(Error::AssignmentMissing)?;
+        let lagrange_coeffs = self.lagrange_interpolator.compute_lagrange_coefficients(t_value)?;
         let mut lagrange_coeffs_FG : Vec<FpGadget<F>> = Vec::new();
         // Now we convert these lagrange coefficients to gadgets, and then constrain them.
         // The i-th lagrange coefficients constraint is:
         // (v_inv[i] * t - v_inv[i] * domain_elem[i]) * (coeff) = 1/Z_I(t)
         // 
-        let vp_t = self.lagrange_interpolator.domain_vp.evaluate_constraints(&mut cs, t);
-        let inv_vp_t = vp_t.inverse(cs.ns(|| "Take inverse of Z_I(t)")).unwrap();
+        let vp_t = self.lagrange_interpolator.domain_vp.evaluate_constraints(&mut cs, t)?;
+        let inv_vp_t = vp_t.inverse(cs.ns(|| "Take inverse of Z_I(t)"))?;
         self.vp_t = Some(vp_t);
         for i in 0..(self.lagrange_interpolator.domain_order)
         {
             let constant = (-self.lagrange_interpolator.all_domain_elems[i]) * self.lagrange_interpolator.v_inv_elems[i];
-            let mut A_element = t.mul_by_constant(&mut cs, &self.lagrange_interpolator.v_inv_elems[i]).unwrap();
-            A_element.add_constant_in_place(&mut cs, &constant);
+            let mut A_element = t.mul_by_constant(&mut cs, &self.lagrange_interpolator.v_inv_elems[i])?;
+            A_element.add_constant_in_place(&mut cs, &constant)?;
             
             let lag_coeff = FpGadget::<F>::alloc(
                 &mut cs.ns(|| format!("generate lagrange coefficient {:?}", i)), 
-                || Ok(lagrange_coeffs[i])).unwrap();
+                || Ok(lagrange_coeffs[i]))?;
                 lagrange_coeffs_FG.push(lag_coeff);
             // Enforce the actual constraint (A_element) * (lagrange_coeff) = 1/Z_I(t)
             A_element.mul_equals(cs.ns(|| format!("Check the {:?}th lagrange coefficient", i)), 
                 &lagrange_coeffs_FG[i], 
-                &inv_vp_t).unwrap();
+                &inv_vp_t)?;
         }
-        return lagrange_coeffs_FG;
+        Ok(lagrange_coeffs_FG)
     }
 
     pub fn interpolate_constraints<CS: ConstraintSystem<F>>(&mut self, 
         mut cs: CS, 
-        interpolation_point: &FpGadget<F>) -> FpGadget<F>
+        interpolation_point: &FpGadget<F>) -> Result<FpGadget<F>, Error>
     {
-        let lagrange_coeffs = self.compute_lagrange_coefficients_constraints(&mut cs, interpolation_point);
+        let lagrange_coeffs = self.compute_lagrange_coefficients_constraints(&mut cs, interpolation_point)?;
         let mut interpolation = FpGadget::<F>::from(&mut cs, &F::zero());
         // Set interpolation to be: sum_{i in domain} lagrange_coeff(i)*f(i)
         for i in 0..self.lagrange_interpolator.domain_order
         {
             let intermediate = lagrange_coeffs[i].mul(
                 cs.ns(|| format!("Compute the product of {:?}th lagrange coefficient and polynomial interpolation", i)),
-                &self.poly_evaluations[i]).unwrap();
-            interpolation = interpolation.add(&mut cs, &intermediate).unwrap();
+                &self.poly_evaluations[i])?;
+            interpolation = interpolation.add(&mut cs, &intermediate)?;
         }
-        interpolation
+        Ok(interpolation)
     }
 }
 
@@ -207,6 +218,7 @@ mod test {
     use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
     use r1cs_core::ConstraintSystem;
     use crate::algebra::lagrange_interpolation::*;
+    use crate::Error;
     use crate::algebra::domain::Domain;
     use algebra_core::fields::*;
 
@@ -261,7 +273,12 @@ mod test {
             L.offset,
             L.gen,
             2,
-            oracle_evals.into_iter().map(|x| x.get_value().unwrap()).collect());
-        assert_eq!(expected, interpolator.interpolate(alpha));
+            oracle_evals.into_iter().map(|x| x.get_value().unwrap()).collect()).unwrap();
+        assert_eq!(expected, interpolator.interpolate(alpha).unwrap());
+        // Interpolating at a point of the domain is an error rather than a panic
+        match interpolator.interpolate(L.offset) {
+            Err(Error::NotInvertible) => (),
+            _ => panic!("interpolating at a domain element should fail"),
+        };
     }
 }
\ No newline at end of file
@@ -2,4 +2,7 @@ pub mod vanishing_polynomial;
 pub mod lagrange_interpolation;
 pub mod domain;
 pub mod polynomial;
-pub mod mux;
\ No newline at end of file
+pub mod mux;
+pub mod bits;
+pub mod extension_field;
+pub mod equality;
@@ -1,23 +1,30 @@
 use algebra::prelude::*;
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{prelude::*};
+use crate::Error;
 
 pub fn mux<F: Field, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(
     mut cs: CS,
     values: &[FG],
-    location: &[Boolean]) -> Result<FG, SynthesisError>
+    location: &[Boolean]) -> Result<FG, Error>
 {
     let N = values.len();
     let n = location.len();
-    // Assert N is a power of 2, and n = log(N)
-    assert!(N & (N - 1) == 0);
-    assert!(1 << n == N);
+    // N must be a power of 2, and n = log(N)
+    if N == 0 || N & (N - 1) != 0
+    {
+        return Err(Error::NotPowerOfTwo(N));
+    }
+    if n >= 64 || 1 << n != N
+    {
+        return Err(Error::InvalidLength{expected: N.trailing_zeros() as usize, found: n});
+    }
     
     let mut cur_mux_values = values.to_vec();
     for i in (0..n)
     {
+        // Each layer halves the values, starting from the 2^n checked above
         let cur_size = 1 << (n - i);
-        assert!(cur_mux_values.len() == cur_size);
 
         let mut next_mux_values = Vec::new();
         for j in (0..cur_size).step_by(2)
@@ -37,6 +44,35 @@ pub fn mux<F: Field, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(
     Ok(cur_mux_values[0].clone())
 }
 
+/// Returns the 2^n selectors of the big-endian n bit location, as field elements,
+/// where selector i is one if location = i and zero otherwise.
+/// Each selector is a product of location bits, and this costs 2^n - 2 constraints for n > 0.
+pub fn one_hot<F: Field, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    location: &[Boolean]) -> Result<Vec<FG>, SynthesisError>
+{
+    let mut selectors = vec![FG::one(&mut cs.ns(|| "one"))?];
+    let zero = FG::zero(&mut cs.ns(|| "zero"))?;
+    for (i, bit) in location.iter().enumerate()
+    {
+        let bit_elem = zero.conditionally_add_constant(&mut cs.ns(|| format!("bit {:?} as field element", i)), bit, F::one())?;
+        // Appending a bit to a prefix p of the location gives the prefixes 2p and 2p + 1.
+        let mut next_selectors = Vec::with_capacity(2 * selectors.len());
+        for j in 0..selectors.len()
+        {
+            let with_bit = if i == 0 {
+                bit_elem.clone()
+            } else {
+                selectors[j].mul(&mut cs.ns(|| format!("bit {:?} prefix {:?}", i, j)), &bit_elem)?
+            };
+            next_selectors.push(selectors[j].sub(&mut cs.ns(|| format!("bit {:?} prefix {:?} without bit", i, j)), &with_bit)?);
+            next_selectors.push(with_bit);
+        }
+        selectors = next_selectors;
+    }
+    Ok(selectors)
+}
+
 // Utility method for testing
 pub fn int_to_constant_boolean_vec(
     index: u64,
@@ -56,9 +92,10 @@ mod test {
     use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
     use r1cs_core::{ConstraintSystem, SynthesisError};
     use crate::algebra::mux::*;
+    use crate::Error;
 
     #[test]
-    fn mux_test() -> Result<(), SynthesisError> {
+    fn mux_test() -> Result<(), Error> {
         use crate::alt_bn128::fr_gadget::FrGadget;
         use crate::alt_bn128::fr::Fr;
         use std::str::FromStr;
@@ -93,4 +130,49 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn one_hot_test() -> Result<(), Error> {
+        use crate::alt_bn128::fr_gadget::FrGadget;
+        use crate::alt_bn128::fr::Fr;
+        use algebra::prelude::*;
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        for i in 0..8u64 {
+            let mut location = Vec::new();
+            for (j, bit) in int_to_constant_boolean_vec(i, 3).iter().enumerate() {
+                location.push(Boolean::alloc(&mut cs.ns(|| format!("location {:?} bit {:?}", i, j)), || Ok(bit.get_value().unwrap()))?);
+            }
+            let num_constraints = cs.num_constraints();
+            let selectors: Vec<FrGadget> = one_hot(&mut cs.ns(|| format!("one hot {:?}", i)), &location)?;
+            assert_eq!(cs.num_constraints() - num_constraints, 6);
+            for j in 0..8 {
+                let expected = if j == i { Fr::one() } else { Fr::zero() };
+                assert_eq!(selectors[j as usize].get_value().unwrap(), expected);
+            }
+        }
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn mux_invalid_size_test() -> Result<(), SynthesisError> {
+        use crate::alt_bn128::fr_gadget::FrGadget;
+        use crate::alt_bn128::fr::Fr;
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let mut f_gadg_vec = Vec::new();
+        for i in 0..3 {
+            f_gadg_vec.push(FrGadget::alloc(&mut cs.ns(|| format!("generate_{:?}", i)), || Ok(Fr::from(i as u32)))?);
+        }
+        match mux(cs.ns(|| "mux 3"), &f_gadg_vec, &int_to_constant_boolean_vec(0, 2)) {
+            Err(Error::NotPowerOfTwo(3)) => (),
+            _ => panic!("mux over 3 values should fail"),
+        };
+        match mux(cs.ns(|| "mux 2"), &f_gadg_vec[0..2], &int_to_constant_boolean_vec(0, 2)) {
+            Err(Error::InvalidLength{expected: 1, found: 2}) => (),
+            _ => panic!("mux with too many location bits should fail"),
+        };
+        Ok(())
+    }
 }
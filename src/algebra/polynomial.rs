@@ -25,7 +25,7 @@ impl<F : Field, FG: FieldGadget<F, F>> DensePolynomial<F, FG> {
         for i in 0..self.coeffs.len()
         {
             let term = cur_pow_x.mul(p_cs.ns(|| format!("c[i] * x^{:?}", i)), &self.coeffs[i])?;
-            res.add_in_place(&mut p_cs, &term);
+            res.add_in_place(&mut p_cs, &term)?;
 
             cur_pow_x = cur_pow_x.mul(p_cs.ns(|| format!("x^{:?}", i)), x)?;
         }
@@ -1,6 +1,7 @@
 use algebra::Field;
 use r1cs_core::ConstraintSystem;
 use r1cs_std::prelude::*;
+use crate::Error;
 
 /// Struct describing vanishing polynomials for a multiplicative coset H,
 /// with |H| a power of 2.
@@ -38,22 +39,22 @@ impl<F : Field> VanishingPolynomial<F> {
     /// Caution for use in holographic lincheck: The output has 2 entries in one matrix
     pub fn evaluate_constraints<CS: ConstraintSystem<F>, FG>(&self, 
         mut cs: CS,
-        x: &FG) -> FG
+        x: &FG) -> Result<FG, Error>
         where FG: FieldGadget<F, F>
     {
         let mut vp_cs = &mut cs.ns(|| "vanishing polynomial");
         if self.dim_h == 1
         {
-            let result = x.sub(&mut vp_cs.ns(|| "compute result"), x).unwrap();
-            return result;
+            let result = x.sub(&mut vp_cs.ns(|| "compute result"), x)?;
+            return Ok(result);
         }
-        let mut cur = x.square(vp_cs.ns(|| format!("compute x^(2^{:?})", 1))).unwrap();
+        let mut cur = x.square(vp_cs.ns(|| format!("compute x^(2^{:?})", 1)))?;
         for i in 1..self.dim_h 
         {
-            cur.square_in_place(vp_cs.ns(|| format!("compute x^(2^{:?})", i + 1))).unwrap();
+            cur.square_in_place(vp_cs.ns(|| format!("compute x^(2^{:?})", i + 1)))?;
         }
-        cur.sub_constant_in_place(vp_cs.ns(|| "compute result"), &self.constant_term).unwrap();
-        cur
+        cur.sub_constant_in_place(vp_cs.ns(|| "compute result"), &self.constant_term)?;
+        Ok(cur)
     }
 }
 
@@ -95,7 +96,7 @@ fn test_vanishing_polynomial_constraints() {
     let vp = VanishingPolynomial::new(h, dim_h);
     let x_fr: Fr = Fr::from(17u32);
     let x = FrGadget::alloc(&mut cs.ns(|| "generate_x"), || Ok(x_fr)).unwrap();
-    let res = vp.evaluate_constraints(&mut cs, &x);
+    let res = vp.evaluate_constraints(&mut cs, &x).unwrap();
     
     // evaluated in sage
     let exp_fr = Fr::from_str("13050942103627446995176210260937927095935575529594931712231973977461351072286").map_err(|_| ()).unwrap();
@@ -0,0 +1,73 @@
+use r1cs_core::SynthesisError;
+use std::fmt;
+
+/// Errors returned by this crate, instead of panicking on malformed inputs.
+#[derive(Debug)]
+pub enum Error {
+    /// An input had the wrong number of elements.
+    InvalidLength{expected: usize, found: usize},
+    /// A size that must be a power of two was not.
+    NotPowerOfTwo(usize),
+    /// A number of leaves was not a power of the tree's arity.
+    NotPowerOfArity{len: usize, arity: usize},
+    /// An index was not smaller than the number of elements it indexes.
+    IndexOutOfBounds{index: usize, len: usize},
+    /// A list of indices was not strictly increasing.
+    UnsortedIndices,
+    /// A gadget needed the value of a variable, but it was not assigned.
+    AssignmentMissing,
+    /// An element that had to be inverted was zero.
+    NotInvertible,
+    /// An authentication tag did not match.
+    AuthenticationFailed,
+    /// A sponge's capacity was too small for what it was asked to hold.
+    CapacityTooSmall{capacity: usize, needed: usize},
+    /// A sponge was forked with the label zero, which would not separate the child from its parent.
+    ZeroForkLabel,
+    /// No nonce satisfies the proof of work condition.
+    ProofOfWorkNotFound{bits: usize},
+    /// A number of bits was zero, or larger than what fits in the target.
+    InvalidBitWidth{bits: usize, max: usize},
+    /// A coset was larger than the domain it is a coset of.
+    CosetTooLarge{coset_dim: usize, dim: usize},
+    /// A tree was deeper than supported.
+    TreeTooDeep{depth: usize, max_depth: usize},
+    /// A parameter that must be non-zero, named by the string, was zero.
+    ZeroParameter(&'static str),
+    /// An error from an underlying gadget.
+    Synthesis(SynthesisError),
+}
+
+impl From<SynthesisError> for Error {
+    fn from(e: SynthesisError) -> Self {
+        match e {
+            SynthesisError::AssignmentMissing => Error::AssignmentMissing,
+            e => Error::Synthesis(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidLength{expected, found} => write!(f, "invalid length: expected {}, found {}", expected, found),
+            Error::NotPowerOfTwo(size) => write!(f, "size {} is not a power of two", size),
+            Error::NotPowerOfArity{len, arity} => write!(f, "size {} is not a power of the arity {}", len, arity),
+            Error::IndexOutOfBounds{index, len} => write!(f, "index {} out of bounds for length {}", index, len),
+            Error::UnsortedIndices => write!(f, "indices are not strictly increasing"),
+            Error::AssignmentMissing => write!(f, "an assignment for a variable could not be computed"),
+            Error::NotInvertible => write!(f, "attempted to invert zero"),
+            Error::AuthenticationFailed => write!(f, "authentication tag mismatch"),
+            Error::CapacityTooSmall{capacity, needed} => write!(f, "sponge capacity {} is too small, {} elements are needed", capacity, needed),
+            Error::ZeroForkLabel => write!(f, "sponges cannot be forked with the label zero"),
+            Error::ProofOfWorkNotFound{bits} => write!(f, "no proof of work nonce with {} zero bits", bits),
+            Error::InvalidBitWidth{bits, max} => write!(f, "invalid bit width {}, must be between 1 and {}", bits, max),
+            Error::CosetTooLarge{coset_dim, dim} => write!(f, "coset of dimension {} is larger than the domain of dimension {}", coset_dim, dim),
+            Error::TreeTooDeep{depth, max_depth} => write!(f, "tree depth {} exceeds the maximum of {}", depth, max_depth),
+            Error::ZeroParameter(name) => write!(f, "{} must be non-zero", name),
+            Error::Synthesis(e) => write!(f, "synthesis error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,180 @@
+use algebra::{prelude::*, FpParameters, UniformRand};
+use r1cs_core::ConstraintSystem;
+use r1cs_std::prelude::*;
+use rand::Rng;
+
+use crate::hashing::*;
+use crate::hashing::sponge::*;
+use crate::hashing::leafhash::*;
+use crate::Error;
+
+/// The number of salt elements needed for `hiding_bits` bits of hiding.
+/// Modeling the sponge as a random oracle, an adversary making q queries learns something about the message
+/// with probability at most q / 2^s, where s is the salt's entropy in bits. Allowing up to 2^hiding_bits queries,
+/// the salt needs 2 * hiding_bits bits of entropy. We count each uniformly random salt element
+/// as `CAPACITY` bits of entropy, which is a lower bound on log|F|, and always use at least one element.
+pub fn salt_len_for_hiding<F: PrimeField>(hiding_bits: usize) -> usize
+{
+    let needed_bits = 2 * hiding_bits;
+    let bits_per_salt_elem = F::Params::CAPACITY as usize;
+    std::cmp::max(1, (needed_bits + bits_per_salt_elem - 1) / bits_per_salt_elem)
+}
+
+/// The opening of a commitment, i.e. its salt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentOpening<F: PrimeField>
+{
+    pub salt: Vec<F>,
+}
+
+/// A hiding commitment to a vector of field elements, computed as LeafHash::salted_hash(msg, salt).
+pub struct Commitment<F: PrimeField, P: Permutation<F> + Clone>
+{
+    leaf_hash: LeafHash<F, P>,
+    salt_len: usize,
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> Commitment<F, P>
+{
+    pub fn new(sponge: AlgebraicSponge<F, P>, salt_len: usize) -> Result<Self, Error>
+    {
+        if salt_len == 0
+        {
+            return Err(Error::ZeroParameter("salt length"));
+        }
+        Ok(Commitment{leaf_hash: LeafHash::new(sponge)?, salt_len})
+    }
+
+    /// Creates a commitment scheme with `hiding_bits` bits of statistical hiding.
+    pub fn with_hiding_level(sponge: AlgebraicSponge<F, P>, hiding_bits: usize) -> Result<Self, Error>
+    {
+        Self::new(sponge, salt_len_for_hiding::<F>(hiding_bits))
+    }
+
+    pub fn salt_len(&self) -> usize
+    {
+        self.salt_len
+    }
+
+    pub fn commit<R: Rng>(&self, msg: &[F], rng: &mut R) -> (F, CommitmentOpening<F>)
+    {
+        let salt: Vec<F> = (0..self.salt_len).map(|_| F::rand(rng)).collect();
+        let com = self.leaf_hash.salted_hash(msg, &salt);
+        (com, CommitmentOpening{salt})
+    }
+
+    pub fn verify(&self, com: F, msg: &[F], opening: &CommitmentOpening<F>) -> bool
+    {
+        opening.salt.len() == self.salt_len && self.leaf_hash.salted_hash(msg, &opening.salt) == com
+    }
+}
+
+pub struct CommitmentGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
+{
+    leaf_hash: LeafHashGadget<F, FG, P>,
+    salt_len: usize,
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> CommitmentGadget<F, FG, P>
+{
+    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, salt_len: usize) -> Result<Self, Error>
+    {
+        if salt_len == 0
+        {
+            return Err(Error::ZeroParameter("salt length"));
+        }
+        Ok(CommitmentGadget{leaf_hash: LeafHashGadget::new(&mut cs, sponge)?, salt_len})
+    }
+
+    /// See `Commitment::with_hiding_level`
+    pub fn with_hiding_level<CS: ConstraintSystem<F>>(cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, hiding_bits: usize) -> Result<Self, Error>
+    {
+        Self::new(cs, sponge, salt_len_for_hiding::<F>(hiding_bits))
+    }
+
+    pub fn salt_len(&self) -> usize
+    {
+        self.salt_len
+    }
+
+    /// Computes the commitment to msg with the given salt.
+    pub fn commit<CS: ConstraintSystem<F>>(&self, mut cs: CS, msg: &[FG], salt: &[FG]) -> Result<FG, Error>
+    {
+        if salt.len() != self.salt_len
+        {
+            return Err(Error::InvalidLength{expected: self.salt_len, found: salt.len()});
+        }
+        Ok(self.leaf_hash.salted_hash(&mut cs, msg, salt)?)
+    }
+
+    /// Enforces that com is a commitment to msg, opened with salt.
+    pub fn verify<CS: ConstraintSystem<F>>(&self, mut cs: CS, com: &FG, msg: &[FG], salt: &[FG]) -> Result<(), Error>
+    {
+        let expected = self.commit(&mut cs.ns(|| "commit"), msg, salt)?;
+        expected.enforce_equal(&mut cs.ns(|| "check commitment"), com)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::hashing::{dummy_permutation::*, sponge::*, commitment::*};
+    use crate::Error;
+
+    #[test]
+    fn salt_len_test() {
+        // 2 * 128 = 256 bits, at 253 bits per element
+        assert_eq!(salt_len_for_hiding::<Fr>(128), 2);
+        assert_eq!(salt_len_for_hiding::<Fr>(126), 1);
+        assert_eq!(salt_len_for_hiding::<Fr>(0), 1);
+    }
+
+    #[test]
+    fn commitment_test() -> Result<(), Error> {
+        let mut rng = XorShiftRng::seed_from_u64(1231275789u64);
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P.clone());
+        assert!(Commitment::new(sponge.clone(), 0).is_err());
+        let scheme = Commitment::with_hiding_level(sponge, 128)?;
+        let msg = vec![Fr::from(1u32), Fr::from(2u32)];
+
+        let (com, opening) = scheme.commit(&msg, &mut rng);
+        assert_eq!(opening.salt.len(), 2);
+        assert!(scheme.verify(com, &msg, &opening));
+        assert!(!scheme.verify(com, &[Fr::from(1u32), Fr::from(3u32)], &opening));
+        // Fresh randomness gives a different commitment to the same message
+        let (com_2, _) = scheme.commit(&msg, &mut rng);
+        assert_ne!(com, com_2);
+
+        for (i, test_msg) in vec![msg.clone(), vec![Fr::from(1u32), Fr::from(3u32)]].into_iter().enumerate() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+                new(&mut cs.ns(|| "sponge"), 2, 1, P.clone())?;
+            let scheme_gadget = CommitmentGadget::with_hiding_level(&mut cs.ns(|| "scheme"), sponge_gadget, 128)?;
+            assert_eq!(scheme_gadget.salt_len(), scheme.salt_len());
+            let com_gadget = FrGadget::alloc(&mut cs.ns(|| "com"), || Ok(com))?;
+            let mut msg_gadget = Vec::new();
+            for j in 0..test_msg.len() {
+                msg_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("msg {:?}", j)), || Ok(test_msg[j]))?);
+            }
+            let mut salt_gadget = Vec::new();
+            for j in 0..opening.salt.len() {
+                salt_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("salt {:?}", j)), || Ok(opening.salt[j]))?);
+            }
+            scheme_gadget.verify(&mut cs.ns(|| "verify"), &com_gadget, &msg_gadget, &salt_gadget)?;
+            assert_eq!(cs.is_satisfied(), i == 0);
+            match scheme_gadget.verify(&mut cs.ns(|| "short salt"), &com_gadget, &msg_gadget, &salt_gadget[1..]) {
+                Err(Error::InvalidLength{expected: 2, found: 1}) => (),
+                _ => panic!("a short salt should be rejected"),
+            };
+        }
+        Ok(())
+    }
+}
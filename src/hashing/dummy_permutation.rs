@@ -46,3 +46,46 @@ impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for SeededDummyPer
         Ok(())
     }
 }
+
+// Like SeededDummyPermutation, but every element is also mixed into every other element,
+// so that the capacity affects the rate after a permutation.
+#[derive(Clone)]
+pub struct MixingDummyPermutation<F: Field> {pub seed: F}
+
+impl<F: Field> Permutation<F> for MixingDummyPermutation<F>
+{
+    fn permute(&self, state: &mut[F])
+    {
+        let mut sum = F::zero();
+        for i in 0..state.len()
+        {
+            sum += state[i];
+        }
+        let mut cur = self.seed;
+        for i in 0..state.len()
+        {
+            state[i] += sum + cur;
+            cur += self.seed;
+        }
+    }
+}
+
+impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for MixingDummyPermutation<F>
+{
+    fn permute<CS: ConstraintSystem<F>>(&self, mut cs: CS, state: &mut[FG]) -> Result<(), SynthesisError>
+    {
+        let mut sum = FG::zero(&mut cs.ns(|| "zero"))?;
+        for i in 0..state.len()
+        {
+            sum.add_in_place(&mut cs.ns(|| format!("sum elem {:?}", i)), &state[i])?;
+        }
+        let mut cur = self.seed;
+        for i in 0..state.len()
+        {
+            state[i].add_in_place(&mut cs.ns(|| format!("add sum to elem {:?}", i)), &sum)?;
+            state[i].add_constant_in_place(&mut cs.ns(|| format!("add seed to elem {:?}", i)), &cur)?;
+            cur += self.seed;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use algebra::prelude::*;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+
+use crate::hashing::*;
+use crate::Error;
+
+/// Authenticated encryption of field elements with a duplex sponge.
+///
+/// The state is initialized with the plaintext length in the first rate element,
+/// and the key and nonce in the first two capacity elements, and then permuted.
+/// Each block of `rate` plaintext elements is added to the rate to produce the ciphertext block,
+/// the ciphertext block stays in the rate, and the state is permuted.
+/// The tag is the first rate element after the final permutation.
+///
+/// A nonce must never be reused with the same key.
+#[derive(Clone)]
+pub struct SpongeEncryption<F: PrimeField, P: Permutation<F>>
+{
+    rate: usize,
+    capacity: usize,
+    permutation: P,
+    _phantom: F,
+}
+
+impl<F: PrimeField, P: Permutation<F>> SpongeEncryption<F, P>
+{
+    pub fn new(rate: usize, capacity: usize, permutation: P) -> Result<Self, Error>
+    {
+        if rate == 0
+        {
+            return Err(Error::ZeroParameter("rate"));
+        }
+        // The key and nonce each take a capacity element
+        if capacity < 2
+        {
+            return Err(Error::CapacityTooSmall{capacity, needed: 2});
+        }
+        Ok(SpongeEncryption{rate, capacity, permutation, _phantom: F::zero()})
+    }
+
+    fn initial_state(&self, key: F, nonce: F, len: usize) -> Vec<F>
+    {
+        let mut state = vec![F::zero(); self.rate + self.capacity];
+        state[0] = F::from(len as u64);
+        state[self.rate] = key;
+        state[self.rate + 1] = nonce;
+        self.permutation.permute(&mut state);
+        state
+    }
+
+    /// Returns the ciphertext, which has the same length as the plaintext, and the tag.
+    pub fn encrypt(&self, key: F, nonce: F, plaintext: &[F]) -> (Vec<F>, F)
+    {
+        let mut state = self.initial_state(key, nonce, plaintext.len());
+        let mut ciphertext = Vec::with_capacity(plaintext.len());
+        for block in plaintext.chunks(self.rate)
+        {
+            for j in 0..block.len()
+            {
+                state[j] += block[j];
+                ciphertext.push(state[j]);
+            }
+            self.permutation.permute(&mut state);
+        }
+        (ciphertext, state[0])
+    }
+
+    /// Returns the plaintext, or Error::AuthenticationFailed if the tag does not match.
+    pub fn decrypt(&self, key: F, nonce: F, ciphertext: &[F], tag: F) -> Result<Vec<F>, Error>
+    {
+        let mut state = self.initial_state(key, nonce, ciphertext.len());
+        let mut plaintext = Vec::with_capacity(ciphertext.len());
+        for block in ciphertext.chunks(self.rate)
+        {
+            for j in 0..block.len()
+            {
+                plaintext.push(block[j] - state[j]);
+                state[j] = block[j];
+            }
+            self.permutation.permute(&mut state);
+        }
+        if state[0] != tag
+        {
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(plaintext)
+    }
+}
+
+/// Enforces that a ciphertext decrypts correctly under SpongeEncryption, including the tag check.
+#[derive(Clone)]
+pub struct DecryptGadget<F: PrimeField, P>
+{
+    rate: usize,
+    capacity: usize,
+    permutation: P,
+    _phantom: F,
+}
+
+impl<F: PrimeField, P> DecryptGadget<F, P>
+{
+    pub fn new(rate: usize, capacity: usize, permutation: P) -> Result<Self, Error>
+    {
+        if rate == 0
+        {
+            return Err(Error::ZeroParameter("rate"));
+        }
+        // The key and nonce each take a capacity element
+        if capacity < 2
+        {
+            return Err(Error::CapacityTooSmall{capacity, needed: 2});
+        }
+        Ok(DecryptGadget{rate, capacity, permutation, _phantom: F::zero()})
+    }
+
+    /// Returns the plaintext, and enforces that the tag is correct.
+    /// Decrypting costs no constraints beyond the permutations, as the plaintext is a linear function of the state.
+    pub fn decrypt<FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        key: &FG,
+        nonce: &FG,
+        ciphertext: &[FG],
+        tag: &FG) -> Result<Vec<FG>, SynthesisError>
+    where
+        P: PermutationGadget<F, FG>
+    {
+        let mut state = Vec::with_capacity(self.rate + self.capacity);
+        for i in 0..(self.rate + self.capacity)
+        {
+            state.push(FG::zero(&mut cs.ns(|| format!("zero {:?}", i)))?);
+        }
+        state[0].add_constant_in_place(&mut cs.ns(|| "length"), &F::from(ciphertext.len() as u64))?;
+        state[self.rate] = key.clone();
+        state[self.rate + 1] = nonce.clone();
+        self.permutation.permute(&mut cs.ns(|| "initial permutation"), &mut state)?;
+
+        let mut plaintext = Vec::with_capacity(ciphertext.len());
+        for (i, block) in ciphertext.chunks(self.rate).enumerate()
+        {
+            let mut cs_i = cs.ns(|| format!("block {:?}", i));
+            for j in 0..block.len()
+            {
+                plaintext.push(block[j].sub(&mut cs_i.ns(|| format!("plaintext {:?}", j)), &state[j])?);
+                state[j] = block[j].clone();
+            }
+            self.permutation.permute(&mut cs_i.ns(|| "permute"), &mut state)?;
+        }
+        state[0].enforce_equal(&mut cs.ns(|| "check tag"), tag)?;
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::{ConstraintSystem, SynthesisError};
+    use algebra::prelude::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::hashing::{dummy_permutation::*, encryption::*};
+    use crate::Error;
+
+    fn alloc_all<CS: ConstraintSystem<Fr>>(mut cs: CS, elems: &[Fr]) -> Result<Vec<FrGadget>, SynthesisError>
+    {
+        let mut res = Vec::new();
+        for i in 0..elems.len() {
+            res.push(FrGadget::alloc(&mut cs.ns(|| format!("elem {:?}", i)), || Ok(elems[i]))?);
+        }
+        Ok(res)
+    }
+
+    #[test]
+    fn encryption_roundtrip_test() -> Result<(), Error> {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        assert!(SpongeEncryption::new(0, 2, P.clone()).is_err());
+        assert!(SpongeEncryption::new(2, 1, P.clone()).is_err());
+        let scheme = SpongeEncryption::new(2, 2, P)?;
+        let key = Fr::from(1234u32);
+        let nonce = Fr::from(1u32);
+        let plaintext: Vec<Fr> = (10..15u32).map(Fr::from).collect();
+
+        let (ciphertext, tag) = scheme.encrypt(key, nonce, &plaintext);
+        assert_eq!(ciphertext.len(), plaintext.len());
+        assert_ne!(ciphertext, plaintext);
+        assert_eq!(scheme.decrypt(key, nonce, &ciphertext, tag)?, plaintext);
+
+        let mut tampered = ciphertext.clone();
+        tampered[4] += Fr::one();
+        match scheme.decrypt(key, nonce, &tampered, tag) {
+            Err(Error::AuthenticationFailed) => (),
+            _ => panic!("tampered ciphertext should not decrypt"),
+        };
+        match scheme.decrypt(key + Fr::one(), nonce, &ciphertext, tag) {
+            Err(Error::AuthenticationFailed) => (),
+            _ => panic!("wrong key should not decrypt"),
+        };
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_gadget_test() -> Result<(), Error> {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let scheme = SpongeEncryption::new(2, 2, P.clone())?;
+        let decrypt_gadget = DecryptGadget::<Fr, _>::new(2, 2, P)?;
+        let key = Fr::from(1234u32);
+        let nonce = Fr::from(1u32);
+        let plaintext: Vec<Fr> = (10..15u32).map(Fr::from).collect();
+        let (ciphertext, tag) = scheme.encrypt(key, nonce, &plaintext);
+
+        for (i, test_tag) in vec![tag, tag + Fr::one()].into_iter().enumerate() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let key_gadget = FrGadget::alloc(&mut cs.ns(|| "key"), || Ok(key))?;
+            let nonce_gadget = FrGadget::alloc(&mut cs.ns(|| "nonce"), || Ok(nonce))?;
+            let tag_gadget = FrGadget::alloc(&mut cs.ns(|| "tag"), || Ok(test_tag))?;
+            let ciphertext_gadget = alloc_all(&mut cs.ns(|| "ciphertext"), &ciphertext)?;
+            let decrypted = decrypt_gadget.decrypt(&mut cs.ns(|| "decrypt"),
+                &key_gadget, &nonce_gadget, &ciphertext_gadget, &tag_gadget)?;
+            assert_eq!(decrypted.iter().map(|x| x.get_value().unwrap()).collect::<Vec<_>>(), plaintext);
+            assert_eq!(cs.is_satisfied(), i == 0);
+        }
+        Ok(())
+    }
+}
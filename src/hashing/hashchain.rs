@@ -1,11 +1,16 @@
-use algebra::prelude::*;
+use algebra::{prelude::*, FpParameters};
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{prelude::*, fields::fp::FpGadget};
 
 use crate::hashing::*;
 use crate::hashing::sponge::*;
+use crate::algebra::extension_field::*;
+use crate::algebra::bits::to_bits_fixed;
+use crate::Error;
 use num_traits::*;
+use rayon::prelude::*;
 
+#[derive(Clone)]
 pub struct HashChain<F: PrimeField, P: Permutation<F> + Clone>
 {
     sponge: AlgebraicSponge<F, P>,
@@ -30,25 +35,95 @@ impl<F: PrimeField, P: Permutation<F> + Clone> HashChain<F, P>
         self.sponge.squeeze(num_elements)
     }
 
-    pub fn squeeze_ints(&mut self, num_elements: usize, bits_per_elem: usize) -> Vec<u64>
+    /// See `AlgebraicSponge::squeeze_bits`
+    pub fn squeeze_bits(&mut self, num_bits: usize) -> Vec<bool>
     {
-        let squeezed_field_elems = self.sponge.squeeze(num_elements);
+        self.sponge.squeeze_bits(num_bits)
+    }
+
+    /// See `AlgebraicSponge::squeeze_bytes`
+    pub fn squeeze_bytes(&mut self, num_bytes: usize) -> Vec<u8>
+    {
+        self.sponge.squeeze_bytes(num_bytes)
+    }
+
+    /// See `AlgebraicSponge::squeeze_ints`
+    pub fn squeeze_ints(&mut self, num_elements: usize, bits_per_int: usize) -> Result<Vec<u64>, Error>
+    {
+        self.sponge.squeeze_ints(num_elements, bits_per_int)
+    }
+
+    /// See `AlgebraicSponge::squeeze_indices`
+    pub fn squeeze_indices(&mut self, count: usize, bits_each: usize) -> Result<Vec<u64>, Error>
+    {
+        self.sponge.squeeze_indices(count, bits_each)
+    }
 
-        let mut vec_of_bits : Vec<Vec<bool>> = squeezed_field_elems.into_iter().map(|x| x.into_repr().to_bits()).collect();
-        print!("{:?}", vec_of_bits);
-        let mut squeezed_ints = Vec::new();
-        for i in 0..num_elements {
-            let mut cur = 0;
-            vec_of_bits[i].reverse();
-            for j in 0..bits_per_elem {
-                cur <<= 1;
-                cur += vec_of_bits[i][j] as u64;
+    /// Squeezes `num_elements` elements of the extension field, each made of `field.degree` squeezed base field elements.
+    /// The i-th squeezed base field element of a challenge is its coefficient of X^i.
+    pub fn squeeze_extension(&mut self, field: &ExtensionField<F>, num_elements: usize) -> Vec<ExtensionFieldElement<F>>
+    {
+        if num_elements == 0
+        {
+            return Vec::new();
+        }
+        let squeezed_elems = self.sponge.squeeze(num_elements * field.degree);
+        squeezed_elems.chunks(field.degree).map(|coeffs| ExtensionFieldElement{coeffs: coeffs.to_vec()}).collect()
+    }
+
+    /// Searches in parallel for the smallest proof of work nonce, such that `verify_pow` accepts it,
+    /// and applies it to this hash chain, exactly as `verify_pow` does.
+    pub fn grind(&mut self, bits: usize) -> Result<u64, Error>
+        where P: Sync
+    {
+        check_pow_bits::<F>(bits)?;
+        // Nonces are searched in batches, in order, so workers never search far past the first valid nonce.
+        const BATCH_SIZE: u64 = 1 << 16;
+        let mut start = 0;
+        loop
+        {
+            let end = start.saturating_add(BATCH_SIZE);
+            let found = (start..end).into_par_iter()
+                .find_first(|nonce| self.clone().verify_pow(*nonce, bits));
+            if let Some(nonce) = found
+            {
+                self.verify_pow(nonce, bits);
+                return Ok(nonce);
             }
-            squeezed_ints.push(cur);
+            if end == u64::max_value()
+            {
+                return Err(Error::ProofOfWorkNotFound{bits});
+            }
+            start = end;
+        }
+    }
+
+    /// Absorbs the nonce, squeezes one element, and checks that it has `bits` leading zeros,
+    /// i.e. that the first `bits` bits of its `MODULUS_BITS`-bit big-endian decomposition are zero.
+    /// More than `MODULUS_BITS` bits are never satisfied.
+    pub fn verify_pow(&mut self, nonce: u64, bits: usize) -> bool
+    {
+        self.sponge.absorb(&[F::from(nonce)]);
+        let elem = self.sponge.squeeze(1)[0];
+        let num_bits = F::Params::MODULUS_BITS as usize;
+        if bits > num_bits
+        {
+            return false;
         }
+        // BigInteger::to_bits is big-endian, so the decomposition is its last MODULUS_BITS bits
+        let repr_bits = elem.into_repr().to_bits();
+        repr_bits[(repr_bits.len() - num_bits)..][..bits].iter().all(|bit| !bit)
+    }
+}
 
-        squeezed_ints
+fn check_pow_bits<F: PrimeField>(bits: usize) -> Result<(), Error>
+{
+    let max_bits = F::Params::MODULUS_BITS as usize;
+    if bits > max_bits
+    {
+        return Err(Error::InvalidBitWidth{bits, max: max_bits});
     }
+    Ok(())
 }
 
 pub struct HashChainGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
@@ -58,16 +133,16 @@ pub struct HashChainGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationG
 
 impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> HashChainGadget<F, FG, P>
 {
-    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Self
+    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Result<Self, SynthesisError>
     {
         let mut sponge_c = sponge.clone();
-        sponge_c.reset(&mut cs);
-        HashChainGadget{sponge: sponge_c}
+        sponge_c.reset(&mut cs)?;
+        Ok(HashChainGadget{sponge: sponge_c})
     }
 
-    pub fn absorb<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, elems: &[FG])
+    pub fn absorb<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, elems: &[FG]) -> Result<(), SynthesisError>
     {
-        self.sponge.absorb(&mut cs, elems);
+        self.sponge.absorb(&mut cs, elems)
     }
 
     pub fn squeeze<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_elements: usize) -> Result<Vec<FG>, SynthesisError>
@@ -75,22 +150,58 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
         self.sponge.squeeze(&mut cs, num_elements)
     }
 
-    pub fn squeeze_ints<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_elements: usize, bits_per_elem: usize) -> Result<Vec<Vec<Boolean>>, SynthesisError>
+    /// See `AlgebraicSpongeGadget::squeeze_bits`
+    pub fn squeeze_bits<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_bits: usize) -> Result<Vec<Boolean>, SynthesisError>
+    {
+        self.sponge.squeeze_bits(&mut cs, num_bits)
+    }
+
+    /// See `AlgebraicSpongeGadget::squeeze_bytes`
+    pub fn squeeze_bytes<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_bytes: usize) -> Result<Vec<UInt8>, SynthesisError>
     {
-        let mut cs_cur = cs.ns(|| "Squeeze ints");
-        let squeezed_field_elems = self.sponge.squeeze(&mut cs_cur, num_elements)?;
+        self.sponge.squeeze_bytes(&mut cs, num_bytes)
+    }
 
-        let mut vec_of_bits = Vec::<Vec<Boolean>>::new();
-        for i in 0..squeezed_field_elems.len(){
-            vec_of_bits.push(squeezed_field_elems[i].to_bits(&mut cs_cur.ns(|| format!("field elem {:?}", i))).unwrap());
-        }
-        let elem_len = vec_of_bits[0].len();
-        for i in 0..num_elements {
-            let mut cur = 0;
-            vec_of_bits[i] = vec_of_bits[i][0..bits_per_elem].to_vec();
+    /// See `AlgebraicSpongeGadget::squeeze_ints`
+    pub fn squeeze_ints<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_elements: usize, bits_per_int: usize) -> Result<Vec<Vec<Boolean>>, Error>
+    {
+        self.sponge.squeeze_ints(&mut cs.ns(|| "Squeeze ints"), num_elements, bits_per_int)
+    }
+
+    /// See `AlgebraicSpongeGadget::squeeze_indices`
+    pub fn squeeze_indices<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, count: usize, bits_each: usize) -> Result<Vec<Vec<Boolean>>, Error>
+    {
+        self.sponge.squeeze_indices(&mut cs.ns(|| "Squeeze indices"), count, bits_each)
+    }
+
+    /// See `HashChain::squeeze_extension`
+    pub fn squeeze_extension<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, field: &ExtensionField<F>, num_elements: usize) -> Result<Vec<ExtensionFieldGadget<F, FG>>, SynthesisError>
+    {
+        if num_elements == 0
+        {
+            return Ok(Vec::new());
         }
+        let squeezed_elems = self.sponge.squeeze(&mut cs.ns(|| "Squeeze extension"), num_elements * field.degree)?;
+        Ok(squeezed_elems.chunks(field.degree).map(|coeffs| ExtensionFieldGadget::from_coeffs(coeffs.to_vec())).collect())
+    }
 
-        Ok(vec_of_bits)
+    /// Enforces the proof of work condition of `HashChain::verify_pow` for the given nonce,
+    /// i.e. that the squeezed element has `bits` leading zeros.
+    /// The nonce is range checked to 64 bits, as natively it is a u64, and the squeezed element
+    /// is decomposed strictly, so the prover cannot fake the zero bits.
+    pub fn verify_pow<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, nonce: &FG, bits: usize) -> Result<(), Error>
+    {
+        check_pow_bits::<F>(bits)?;
+        to_bits_fixed(&mut cs.ns(|| "nonce is 64 bits"), nonce, 64)?;
+        self.sponge.absorb(&mut cs.ns(|| "absorb nonce"), &[nonce.clone()])?;
+        let elem = self.sponge.squeeze(&mut cs.ns(|| "squeeze pow element"), 1)?[0].clone();
+        // to_bits_strict is big-endian, so the leading bits come first
+        let elem_bits = elem.to_bits_strict(&mut cs.ns(|| "pow element bits"))?;
+        for i in 0..bits
+        {
+            elem_bits[i].enforce_equal(&mut cs.ns(|| format!("pow bit {:?} is zero", i)), &Boolean::constant(false))?;
+        }
+        Ok(())
     }
 }
 
@@ -103,24 +214,178 @@ mod test {
 
     use algebra::{FpParameters, prelude::*};
     use r1cs_std::prelude::*;
-    use r1cs_core::SynthesisError;
     use r1cs_std::alloc::*;
     use r1cs_std::eq::EqGadget;
     use crate::alt_bn128::fr_gadget::FrGadget;
     use crate::alt_bn128::fr::Fr;
     use std::str::FromStr;
     use crate::hashing::{*, dummy_permutation::*, sponge::*, hashchain::*};
+    use crate::Error;
 
     #[test]
-    fn hashchain_consistency_test() -> Result<(), SynthesisError> {
+    fn hashchain_consistency_test() -> Result<(), Error> {
         let rate = 3;
         let capacity = 1;
         let P = DummyPermutation{};
         let sponge = AlgebraicSponge::<Fr, DummyPermutation>::new(rate, capacity, P);
         let mut hashchain = HashChain::new(sponge);
         hashchain.absorb(&[Fr::one(), Fr::one() + Fr::one()]);
-        let res = hashchain.squeeze_ints(2, 1);
+        let res = hashchain.squeeze_ints(2, 1)?;
         assert_eq!(res, vec![0, 1]);        
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn hashchain_squeeze_native_gadget_consistency_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let rate = 2;
+        let capacity = 1;
+        let P = SeededDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(rate, capacity, P.clone());
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, SeededDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), rate, capacity, P)?;
+        let mut hashchain = HashChain::new(sponge);
+        let mut hashchain_gadget = HashChainGadget::new(&mut cs.ns(|| "hashchain"), sponge_gadget)?;
+
+        // Use an element with high bits set, so truncation to the capacity is exercised
+        let input = vec![-Fr::from(7u32), Fr::from(12345u32)];
+        let input_gadget = vec![
+            FrGadget::alloc(&mut cs.ns(|| "input 0"), || Ok(input[0]))?,
+            FrGadget::alloc(&mut cs.ns(|| "input 1"), || Ok(input[1]))?];
+        hashchain.absorb(&input);
+        hashchain_gadget.absorb(&mut cs.ns(|| "absorb"), &input_gadget)?;
+
+        let mut elem_hashchain = hashchain.clone();
+        let bits = hashchain.squeeze_bits(300);
+        let bits_gadget = hashchain_gadget.squeeze_bits(&mut cs.ns(|| "squeeze bits"), 300)?;
+        assert_eq!(bits.len(), 300);
+        // Each element contributes its CAPACITY - SQUEEZE_SECURITY_BITS least significant bits
+        let bits_per_elem = <Fr as PrimeField>::Params::CAPACITY as usize - SQUEEZE_SECURITY_BITS;
+        assert_eq!(bits_per_elem, 125);
+        let mut elem_bits = elem_hashchain.squeeze(1)[0].into_repr().to_bits();
+        elem_bits.reverse();
+        assert_eq!(bits[0..bits_per_elem].to_vec(), elem_bits[0..bits_per_elem].to_vec());
+        assert_eq!(bits, bits_gadget.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>());
+
+        let bytes = hashchain.squeeze_bytes(5);
+        let bytes_gadget = hashchain_gadget.squeeze_bytes(&mut cs.ns(|| "squeeze bytes"), 5)?;
+        assert_eq!(bytes, bytes_gadget.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>());
+
+        let ints = hashchain.squeeze_ints(3, 10)?;
+        let ints_gadget = hashchain_gadget.squeeze_ints(&mut cs.ns(|| "squeeze ints"), 3, 10)?;
+        for i in 0..3 {
+            let expected = int_to_constant_boolean_vec(ints[i], 10);
+            assert_eq!(
+                expected.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>(),
+                ints_gadget[i].iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>());
+        }
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn hashchain_squeeze_indices_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let rate = 2;
+        let capacity = 1;
+        let P = SeededDummyPermutation{seed: -Fr::from(5u32)};
+        let sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(rate, capacity, P.clone());
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, SeededDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), rate, capacity, P)?;
+        let mut hashchain = HashChain::new(sponge.clone());
+        let mut bits_hashchain = HashChain::new(sponge);
+        let mut hashchain_gadget = HashChainGadget::new(&mut cs.ns(|| "hashchain"), sponge_gadget)?;
+
+        // 6 indices of 20 bits fit in the 125 usable bits of one element, so 30 indices take 5 elements.
+        assert_eq!(indices_per_elem::<Fr>(20)?, 6);
+        let indices = hashchain.squeeze_indices(30, 20)?;
+        let indices_gadget = hashchain_gadget.squeeze_indices(&mut cs.ns(|| "squeeze indices"), 30, 20)?;
+        assert_eq!(indices.len(), 30);
+        assert_eq!(indices_gadget.len(), 30);
+        for i in 0..30 {
+            assert!(indices[i] < (1 << 20));
+            let expected = int_to_constant_boolean_vec(indices[i], 20);
+            assert_eq!(
+                expected.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>(),
+                indices_gadget[i].iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>());
+        }
+
+        // The indices of the first element are consecutive chunks of its bits
+        let bits = bits_hashchain.squeeze_bits(6 * 20);
+        for i in 0..6 {
+            let mut expected = 0;
+            for j in (0..20).rev() {
+                expected = (expected << 1) + bits[20 * i + j] as u64;
+            }
+            assert_eq!(indices[i], expected);
+        }
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn hashchain_grind_test() -> Result<(), Error> {
+        let rate = 2;
+        let capacity = 1;
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(rate, capacity, P.clone());
+        let mut prover_hashchain = HashChain::new(sponge);
+        prover_hashchain.absorb(&[Fr::from(17u32)]);
+        let mut verifier_hashchain = prover_hashchain.clone();
+        let mut bad_verifier_hashchain = prover_hashchain.clone();
+
+        let nonce = prover_hashchain.grind(8)?;
+        let mut pow_hashchain = verifier_hashchain.clone();
+        pow_hashchain.absorb(&[Fr::from(nonce)]);
+        let pow_bits = pow_hashchain.squeeze(1)[0].into_repr().to_bits();
+        // The 256 bit representation has two more leading zeros than the 254 bit decomposition
+        assert!(pow_bits[0..10].iter().all(|bit| !bit));
+        assert!(verifier_hashchain.verify_pow(nonce, 8));
+        assert!(!bad_verifier_hashchain.verify_pow(nonce + 1, 8));
+        // Grinding advances the prover exactly like verification advances the verifier
+        assert_eq!(prover_hashchain.squeeze(1), verifier_hashchain.squeeze(1));
+
+        // The same nonce plus 2^64 is not a u64, and must be rejected
+        let wide_nonce = Fr::from(nonce) + Fr::from(u64::max_value()) + Fr::one();
+        let cases = vec![(Fr::from(nonce), true), (Fr::from(nonce + 1), false), (wide_nonce, false)];
+        for (i, (test_nonce, satisfied)) in cases.into_iter().enumerate() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+                new(&mut cs.ns(|| "sponge"), rate, capacity, P.clone())?;
+            let mut hashchain_gadget = HashChainGadget::new(&mut cs.ns(|| "hashchain"), sponge_gadget)?;
+            let input = FrGadget::alloc(&mut cs.ns(|| "input"), || Ok(Fr::from(17u32)))?;
+            hashchain_gadget.absorb(&mut cs.ns(|| "absorb"), &[input])?;
+            let nonce_gadget = FrGadget::alloc(&mut cs.ns(|| "nonce"), || Ok(test_nonce))?;
+            hashchain_gadget.verify_pow(&mut cs.ns(|| "pow"), &nonce_gadget, 8)?;
+            assert_eq!(cs.is_satisfied(), satisfied, "case {:?}", i);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn hashchain_squeeze_extension_test() -> Result<(), Error> {
+        use crate::algebra::extension_field::*;
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P.clone());
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, P)?;
+        let mut hashchain = HashChain::new(sponge.clone());
+        let mut base_hashchain = HashChain::new(sponge);
+        let mut hashchain_gadget = HashChainGadget::new(&mut cs.ns(|| "hashchain"), sponge_gadget)?;
+
+        let field = ExtensionField::new(3, Fr::from(5u32))?;
+        let challenges = hashchain.squeeze_extension(&field, 2);
+        let challenges_gadget = hashchain_gadget.squeeze_extension(&mut cs.ns(|| "squeeze"), &field, 2)?;
+        let base_elems = base_hashchain.squeeze(6);
+        for i in 0..2 {
+            assert_eq!(challenges[i].coeffs, base_elems[(3 * i)..(3 * i + 3)].to_vec());
+            assert_eq!(challenges_gadget[i].get_value().unwrap(), challenges[i]);
+        }
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use algebra::prelude::*;
+use r1cs_core::ConstraintSystem;
+use r1cs_std::prelude::*;
+
+use crate::hashing::*;
+use crate::hashing::sponge::*;
+use crate::Error;
+
+fn check_arity(arity: usize) -> Result<(), Error>
+{
+    if arity < 2 || arity & (arity - 1) != 0
+    {
+        return Err(Error::NotPowerOfTwo(arity));
+    }
+    Ok(())
+}
+
+/// Hashes the k children of a node in a k-ary Merkle tree, where k is a power of two.
+/// Picking a sponge whose rate is at least k makes this a single permutation,
+/// e.g. the width-17 Poseidon instance for a 16-ary tree.
+pub struct KToOneHash<F: PrimeField, P: Permutation<F> + Clone>
+{
+    sponge: AlgebraicSponge<F, P>,
+    arity: usize,
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> KToOneHash<F, P>
+{
+    pub fn new(sponge: AlgebraicSponge<F, P>, arity: usize) -> Result<Self, Error>
+    {
+        Self::new_with_tree_depth(sponge, arity, 0)
+    }
+
+    /// The sponge's capacity is initialized to the node hash IV for this arity, see `merkle_arity_capacity_iv`.
+    pub fn new_with_tree_depth(sponge: AlgebraicSponge<F, P>, arity: usize, tree_depth: usize) -> Result<Self, Error>
+    {
+        check_arity(arity)?;
+        let sponge_c = sponge.clone().with_capacity_iv(merkle_arity_capacity_iv(MerkleHashDomain::Node, tree_depth, arity))?;
+        Ok(KToOneHash{sponge: sponge_c, arity})
+    }
+
+    pub fn hash(&self, children: &[F]) -> Result<F, Error>
+    {
+        if children.len() != self.arity
+        {
+            return Err(Error::InvalidLength{expected: self.arity, found: children.len()});
+        }
+        let mut sponge_c = self.sponge.clone();
+        sponge_c.absorb(children);
+        // TODO: Make generic for smaller fields
+        Ok(sponge_c.squeeze(1)[0])
+    }
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> KToOneHasher<F> for KToOneHash<F, P>
+{
+    fn arity(&self) -> usize
+    {
+        self.arity
+    }
+
+    fn hash_children(&self, children: &[F]) -> Result<F, Error>
+    {
+        self.hash(children)
+    }
+}
+
+pub struct KToOneHashGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
+{
+    sponge: AlgebraicSpongeGadget<F, FG, P>,
+    arity: usize,
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> KToOneHashGadget<F, FG, P>
+{
+    pub fn new<CS: ConstraintSystem<F>>(cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, arity: usize) -> Result<Self, Error>
+    {
+        Self::new_with_tree_depth(cs, sponge, arity, 0)
+    }
+
+    /// See `KToOneHash::new_with_tree_depth`
+    pub fn new_with_tree_depth<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, arity: usize, tree_depth: usize) -> Result<Self, Error>
+    {
+        check_arity(arity)?;
+        let sponge_c = sponge.clone().with_capacity_iv(&mut cs, merkle_arity_capacity_iv(MerkleHashDomain::Node, tree_depth, arity))?;
+        Ok(KToOneHashGadget{sponge: sponge_c, arity})
+    }
+
+    pub fn hash<CS: ConstraintSystem<F>>(&self, mut cs: CS, children: &[FG]) -> Result<FG, Error>
+    {
+        if children.len() != self.arity
+        {
+            return Err(Error::InvalidLength{expected: self.arity, found: children.len()});
+        }
+        let mut sponge_c = self.sponge.clone();
+        sponge_c.absorb(&mut cs, children)?;
+        // TODO: Make generic for smaller fields
+        Ok(sponge_c.squeeze(&mut cs, 1)?[0].clone())
+    }
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> KToOneHasherGadget<F, FG> for KToOneHashGadget<F, FG, P>
+{
+    fn arity(&self) -> usize
+    {
+        self.arity
+    }
+
+    fn hash_children<CS: ConstraintSystem<F>>(&self, cs: CS, children: &[FG]) -> Result<FG, Error>
+    {
+        self.hash(cs, children)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::hashing::{dummy_permutation::*, sponge::*, two_to_one_hash::*, k_to_one_hash::*};
+    use crate::Error;
+
+    #[test]
+    fn k_to_one_hash_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(4, 1, permutation.clone());
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 4, 1, permutation)?;
+
+        // A binary KToOneHash is the TwoToOneHash
+        let a = Fr::from(5u32);
+        let b = Fr::from(7u32);
+        assert_eq!(KToOneHash::new(sponge.clone(), 2)?.hash(&[a, b])?, TwoToOneHash::new(sponge.clone())?.hash(a, b));
+
+        // Zero padding a binary node would collide with a 4-ary node, if the IV didn't bind the arity
+        let four_to_one = KToOneHash::new(sponge.clone(), 4)?;
+        assert!(KToOneHash::new(sponge.clone(), 3).is_err());
+        let children = vec![a, b, Fr::zero(), Fr::zero()];
+        assert_ne!(four_to_one.hash(&children)?, KToOneHash::new(sponge, 2)?.hash(&[a, b])?);
+
+        let hasher_gadget = KToOneHashGadget::new(&mut cs.ns(|| "hasher"), sponge_gadget, 4)?;
+        let mut children_gadget = Vec::new();
+        for i in 0..children.len() {
+            children_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("child {:?}", i)), || Ok(children[i]))?);
+        }
+        let hash_gadget = hasher_gadget.hash(&mut cs.ns(|| "hash"), &children_gadget)?;
+        assert_eq!(hash_gadget.get_value().unwrap(), four_to_one.hash(&children)?);
+        assert!(cs.is_satisfied());
+
+        // The number of children must match the arity
+        match hasher_gadget.hash(&mut cs.ns(|| "short hash"), &children_gadget[1..]) {
+            Err(Error::InvalidLength{expected: 4, found: 3}) => (),
+            _ => panic!("hashing 3 children should fail for a 4-ary hash"),
+        };
+        assert!(four_to_one.hash(&children[1..]).is_err());
+        Ok(())
+    }
+}
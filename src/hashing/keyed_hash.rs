@@ -0,0 +1,193 @@
+use algebra::prelude::*;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+
+use crate::hashing::*;
+use crate::hashing::sponge::*;
+
+// Domain separators, absorbed first so that a PRF output can never be used as a MAC tag, or vice versa.
+const PRF_DOMAIN: u64 = 1;
+const MAC_DOMAIN: u64 = 2;
+
+// Absorbs [domain, key, input length] followed by the input, and squeezes one element.
+// The length is needed so that inputs with trailing zeros do not collide.
+fn keyed_hash<F: PrimeField, P: Permutation<F> + Clone>(sponge: &AlgebraicSponge<F, P>, domain: u64, key: F, input: &[F]) -> F
+{
+    let mut sponge_copy = sponge.clone();
+    sponge_copy.absorb(&[F::from(domain), key, F::from(input.len() as u64)]);
+    sponge_copy.absorb(input);
+    sponge_copy.squeeze(1)[0]
+}
+
+fn keyed_hash_gadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    sponge: &AlgebraicSpongeGadget<F, FG, P>,
+    domain: u64,
+    key: &FG,
+    input: &[FG]) -> Result<FG, SynthesisError>
+{
+    let mut sponge_copy = sponge.clone();
+    let zero = FG::zero(&mut cs.ns(|| "zero"))?;
+    let domain = zero.add_constant(&mut cs.ns(|| "domain"), &F::from(domain))?;
+    let len = zero.add_constant(&mut cs.ns(|| "input length"), &F::from(input.len() as u64))?;
+    sponge_copy.absorb(&mut cs.ns(|| "absorb key"), &[domain, key.clone(), len])?;
+    sponge_copy.absorb(&mut cs.ns(|| "absorb input"), input)?;
+    Ok(sponge_copy.squeeze(&mut cs.ns(|| "squeeze"), 1)?[0].clone())
+}
+
+/// A pseudorandom function keyed by a single field element, e.g. for deriving nullifiers.
+/// The key is absorbed in the first rate block.
+pub struct Prf<F: PrimeField, P: Permutation<F> + Clone>
+{
+    sponge: AlgebraicSponge<F, P>,
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> Prf<F, P>
+{
+    pub fn new(sponge: AlgebraicSponge<F, P>) -> Self
+    {
+        let mut sponge_copy = sponge.clone();
+        sponge_copy.reset();
+        Prf{sponge: sponge_copy}
+    }
+
+    pub fn eval(&self, key: F, input: &[F]) -> F
+    {
+        keyed_hash(&self.sponge, PRF_DOMAIN, key, input)
+    }
+}
+
+/// A message authentication code keyed by a single field element.
+pub struct Mac<F: PrimeField, P: Permutation<F> + Clone>
+{
+    sponge: AlgebraicSponge<F, P>,
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> Mac<F, P>
+{
+    pub fn new(sponge: AlgebraicSponge<F, P>) -> Self
+    {
+        let mut sponge_copy = sponge.clone();
+        sponge_copy.reset();
+        Mac{sponge: sponge_copy}
+    }
+
+    pub fn tag(&self, key: F, message: &[F]) -> F
+    {
+        keyed_hash(&self.sponge, MAC_DOMAIN, key, message)
+    }
+
+    pub fn verify(&self, key: F, message: &[F], tag: F) -> bool
+    {
+        self.tag(key, message) == tag
+    }
+}
+
+pub struct PrfGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
+{
+    sponge: AlgebraicSpongeGadget<F, FG, P>,
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> PrfGadget<F, FG, P>
+{
+    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Result<Self, SynthesisError>
+    {
+        let mut sponge_copy = sponge.clone();
+        sponge_copy.reset(&mut cs)?;
+        Ok(PrfGadget{sponge: sponge_copy})
+    }
+
+    pub fn eval<CS: ConstraintSystem<F>>(&self, cs: CS, key: &FG, input: &[FG]) -> Result<FG, SynthesisError>
+    {
+        keyed_hash_gadget(cs, &self.sponge, PRF_DOMAIN, key, input)
+    }
+}
+
+pub struct MacGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
+{
+    sponge: AlgebraicSpongeGadget<F, FG, P>,
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> MacGadget<F, FG, P>
+{
+    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Result<Self, SynthesisError>
+    {
+        let mut sponge_copy = sponge.clone();
+        sponge_copy.reset(&mut cs)?;
+        Ok(MacGadget{sponge: sponge_copy})
+    }
+
+    pub fn tag<CS: ConstraintSystem<F>>(&self, cs: CS, key: &FG, message: &[FG]) -> Result<FG, SynthesisError>
+    {
+        keyed_hash_gadget(cs, &self.sponge, MAC_DOMAIN, key, message)
+    }
+
+    /// Enforces that tag is the MAC of message under key.
+    pub fn verify<CS: ConstraintSystem<F>>(&self, mut cs: CS, key: &FG, message: &[FG], tag: &FG) -> Result<(), SynthesisError>
+    {
+        let expected = self.tag(&mut cs.ns(|| "compute tag"), key, message)?;
+        expected.enforce_equal(&mut cs.ns(|| "check tag"), tag)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::{ConstraintSystem, SynthesisError};
+    use algebra::prelude::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::hashing::{dummy_permutation::*, sponge::*, keyed_hash::*};
+
+    #[test]
+    fn prf_mac_test() {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P);
+        let prf = Prf::new(sponge.clone());
+        let mac = Mac::new(sponge);
+        let key = Fr::from(1234u32);
+        let input = vec![Fr::from(5u32)];
+
+        assert_ne!(prf.eval(key, &input), prf.eval(key + Fr::one(), &input));
+        // Trailing zeros are not ignored
+        assert_ne!(prf.eval(key, &input), prf.eval(key, &[Fr::from(5u32), Fr::zero()]));
+        // The PRF and MAC are domain separated
+        assert_ne!(prf.eval(key, &input), mac.tag(key, &input));
+
+        let tag = mac.tag(key, &input);
+        assert!(mac.verify(key, &input, tag));
+        assert!(!mac.verify(key, &input, tag + Fr::one()));
+    }
+
+    #[test]
+    fn prf_mac_gadget_test() -> Result<(), SynthesisError> {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P.clone());
+        let prf = Prf::new(sponge.clone());
+        let mac = Mac::new(sponge);
+        let key = Fr::from(1234u32);
+        let input = vec![Fr::from(5u32), Fr::from(6u32), Fr::from(7u32)];
+        let tag = mac.tag(key, &input);
+
+        for (i, test_tag) in vec![tag, tag + Fr::one()].into_iter().enumerate() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+                new(&mut cs.ns(|| "sponge"), 2, 1, P.clone())?;
+            let prf_gadget = PrfGadget::new(&mut cs.ns(|| "prf"), sponge_gadget.clone())?;
+            let mac_gadget = MacGadget::new(&mut cs.ns(|| "mac"), sponge_gadget)?;
+
+            let key_gadget = FrGadget::alloc(&mut cs.ns(|| "key"), || Ok(key))?;
+            let mut input_gadget = Vec::new();
+            for j in 0..input.len() {
+                input_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("input {:?}", j)), || Ok(input[j]))?);
+            }
+            let tag_gadget = FrGadget::alloc(&mut cs.ns(|| "tag"), || Ok(test_tag))?;
+
+            let prf_out = prf_gadget.eval(&mut cs.ns(|| "eval prf"), &key_gadget, &input_gadget)?;
+            assert_eq!(prf_out.get_value().unwrap(), prf.eval(key, &input));
+            mac_gadget.verify(&mut cs.ns(|| "verify mac"), &key_gadget, &input_gadget, &tag_gadget)?;
+            assert_eq!(cs.is_satisfied(), i == 0);
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use r1cs_std::{prelude::*, fields::fp::FpGadget};
 
 use crate::hashing::*;
 use crate::hashing::sponge::*;
+use crate::Error;
 use num_traits::*;
 
 pub struct LeafHash<F: PrimeField, P: Permutation<F> + Clone>
@@ -13,11 +14,16 @@ pub struct LeafHash<F: PrimeField, P: Permutation<F> + Clone>
 
 impl<F: PrimeField, P: Permutation<F> + Clone> LeafHash<F, P>
 {
-    pub fn new(sponge: AlgebraicSponge<F, P>) -> Self
+    pub fn new(sponge: AlgebraicSponge<F, P>) -> Result<Self, Error>
     {
-        let mut sponge_copy = sponge.clone();
-        sponge_copy.reset();
-        LeafHash{sponge: sponge_copy}
+        Self::new_with_tree_depth(sponge, 0)
+    }
+
+    /// The sponge's capacity is initialized to the leaf hash IV, see `merkle_capacity_iv`.
+    pub fn new_with_tree_depth(sponge: AlgebraicSponge<F, P>, tree_depth: usize) -> Result<Self, Error>
+    {
+        let sponge_copy = sponge.clone().with_capacity_iv(merkle_capacity_iv(MerkleHashDomain::Leaf, tree_depth))?;
+        Ok(LeafHash{sponge: sponge_copy})
     }
 
     pub fn hash(&self, elems: &[F]) -> F
@@ -29,15 +35,29 @@ impl<F: PrimeField, P: Permutation<F> + Clone> LeafHash<F, P>
     }
 
     pub fn zk_hash(&self, elems: &[F], salt: F) -> F
+    {
+        self.salted_hash(elems, &[salt])
+    }
+
+    /// Like zk_hash, but with a salt of several elements, for when a single element is not enough for hiding.
+    pub fn salted_hash(&self, elems: &[F], salt: &[F]) -> F
     {
         let mut sponge_copy = self.sponge.clone();
         sponge_copy.absorb(elems);
-        sponge_copy.absorb(&[salt]);
+        sponge_copy.absorb(salt);
         // TODO: Make generic for smaller fields
         sponge_copy.squeeze(1)[0]
     }
 }
 
+impl<F: PrimeField, P: Permutation<F> + Clone> LeafHasher<F> for LeafHash<F, P>
+{
+    fn hash_leaf(&self, leaf: &[F]) -> F
+    {
+        self.hash(leaf)
+    }
+}
+
 pub struct LeafHashGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
 {
     sponge: AlgebraicSpongeGadget<F, FG, P>,
@@ -45,27 +65,46 @@ pub struct LeafHashGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGa
 
 impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> LeafHashGadget<F, FG, P>
 {
-    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Self
+    pub fn new<CS: ConstraintSystem<F>>(cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Result<Self, Error>
     {
-        let mut sponge_copy = sponge.clone();
-        sponge_copy.reset(&mut cs);
-        LeafHashGadget{sponge: sponge_copy}
+        Self::new_with_tree_depth(cs, sponge, 0)
+    }
+
+    /// See `LeafHash::new_with_tree_depth`
+    pub fn new_with_tree_depth<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, tree_depth: usize) -> Result<Self, Error>
+    {
+        let sponge_copy = sponge.clone().with_capacity_iv(&mut cs, merkle_capacity_iv(MerkleHashDomain::Leaf, tree_depth))?;
+        Ok(LeafHashGadget{sponge: sponge_copy})
     }
 
     pub fn hash<CS: ConstraintSystem<F>>(&self, mut cs: CS, elems: &[FG]) -> Result<FG, SynthesisError>
     {
         let mut sponge_copy = self.sponge.clone();
-        sponge_copy.absorb(&mut cs, elems);
+        sponge_copy.absorb(&mut cs, elems)?;
         // TODO: Make generic for smaller fields
         Ok(sponge_copy.squeeze(&mut cs, 1)?[0].clone())
     }
 
-    pub fn zk_hash<CS: ConstraintSystem<F>>(&self, mut cs: CS, elems: &[FG], salt: FG) -> Result<FG, SynthesisError>
+    pub fn zk_hash<CS: ConstraintSystem<F>>(&self, cs: CS, elems: &[FG], salt: FG) -> Result<FG, SynthesisError>
+    {
+        self.salted_hash(cs, elems, &[salt])
+    }
+
+    /// See `LeafHash::salted_hash`
+    pub fn salted_hash<CS: ConstraintSystem<F>>(&self, mut cs: CS, elems: &[FG], salt: &[FG]) -> Result<FG, SynthesisError>
     {
         let mut sponge_copy = self.sponge.clone();
-        sponge_copy.absorb(&mut cs, elems);
-        sponge_copy.absorb(&mut cs, &[salt]);
+        sponge_copy.absorb(&mut cs, elems)?;
+        sponge_copy.absorb(&mut cs, salt)?;
         // TODO: Make generic for smaller fields
         Ok(sponge_copy.squeeze(&mut cs, 1)?[0].clone())
     }
-}
\ No newline at end of file
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> LeafHasherGadget<F, FG> for LeafHashGadget<F, FG, P>
+{
+    fn hash_leaf<CS: ConstraintSystem<F>>(&self, cs: CS, leaf: &[FG]) -> Result<FG, SynthesisError>
+    {
+        self.hash(cs, leaf)
+    }
+}
@@ -5,10 +5,17 @@ pub mod poseidon;
 pub mod rescue;
 pub mod dummy_permutation;
 pub mod two_to_one_hash;
+pub mod k_to_one_hash;
+pub mod transcript;
+pub mod sponge_rng;
+pub mod encryption;
+pub mod keyed_hash;
+pub mod commitment;
 
-use algebra::fields::Field;
+use algebra::fields::{Field, PrimeField};
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{prelude::*};
+use crate::Error;
 
 // Const Generics aren't stable, so the size parameter cannot be templated.
 pub trait Permutation<F: Field>
@@ -28,4 +35,92 @@ where
         &self, 
         cs: CS, 
         state: &mut [FG]) -> Result<(), SynthesisError>;
-}
\ No newline at end of file
+}
+
+/// Hashes a Merkle tree leaf to its digest.
+/// Hashes whose output is not a field element (e.g. Blake2) should pack their digest into one.
+pub trait LeafHasher<F: PrimeField>
+{
+    fn hash_leaf(&self, leaf: &[F]) -> F;
+}
+
+/// Compresses the digests of two sibling Merkle tree nodes into the digest of their parent.
+pub trait NodeHasher<F: PrimeField>
+{
+    fn hash_node(&self, left: F, right: F) -> F;
+}
+
+/// Compresses the digests of the k children of a k-ary Merkle tree node into the digest of the node.
+pub trait KToOneHasher<F: PrimeField>
+{
+    fn arity(&self) -> usize;
+
+    fn hash_children(&self, children: &[F]) -> Result<F, Error>;
+}
+
+/// The in-circuit counterpart of LeafHasher.
+pub trait LeafHasherGadget<F: PrimeField, FG: FieldGadget<F, F>>
+{
+    fn hash_leaf<CS: ConstraintSystem<F>>(
+        &self,
+        cs: CS,
+        leaf: &[FG]) -> Result<FG, SynthesisError>;
+}
+
+/// The in-circuit counterpart of NodeHasher.
+pub trait NodeHasherGadget<F: PrimeField, FG: FieldGadget<F, F>>
+{
+    fn hash_node<CS: ConstraintSystem<F>>(
+        &self,
+        cs: CS,
+        left: FG,
+        right: FG) -> Result<FG, SynthesisError>;
+}
+
+// Lets a gadget borrow a node hasher, e.g. to build a MerklePathGadget from one it is part of.
+impl<'a, F: PrimeField, FG: FieldGadget<F, F>, NH: NodeHasherGadget<F, FG>> NodeHasherGadget<F, FG> for &'a NH
+{
+    fn hash_node<CS: ConstraintSystem<F>>(
+        &self,
+        cs: CS,
+        left: FG,
+        right: FG) -> Result<FG, SynthesisError>
+    {
+        NH::hash_node(*self, cs, left, right)
+    }
+}
+
+/// The in-circuit counterpart of KToOneHasher.
+pub trait KToOneHasherGadget<F: PrimeField, FG: FieldGadget<F, F>>
+{
+    fn arity(&self) -> usize;
+
+    fn hash_children<CS: ConstraintSystem<F>>(
+        &self,
+        cs: CS,
+        children: &[FG]) -> Result<FG, Error>;
+}
+
+/// Distinguishes the hashes used inside a Merkle tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MerkleHashDomain {
+    Leaf = 1,
+    Node = 2,
+}
+
+/// The capacity IV for a Merkle tree hash of the given domain, for a tree of the given depth.
+/// Leaf and node hashes get distinct IVs even when they share a permutation, so a leaf can never be
+/// passed off as an inner node. Binding the depth separates trees of different heights.
+/// A depth of 0 means the hash is not bound to a particular depth.
+pub fn merkle_capacity_iv<F: PrimeField>(domain: MerkleHashDomain, tree_depth: usize) -> Vec<F>
+{
+    vec![F::from(((tree_depth as u64) << 8) | domain as u64)]
+}
+
+/// Like merkle_capacity_iv, but also binds the arity of the tree, which must be a power of two.
+/// The arity is encoded as log2(arity) - 1, so binary trees get the same IV as from merkle_capacity_iv.
+pub fn merkle_arity_capacity_iv<F: PrimeField>(domain: MerkleHashDomain, tree_depth: usize, arity: usize) -> Vec<F>
+{
+    let log_arity = arity.trailing_zeros() as u64;
+    vec![F::from(((log_arity - 1) << 48) | ((tree_depth as u64) << 8) | domain as u64)]
+}
@@ -36,12 +36,13 @@ impl<F: Field> PoseidonPermutation<F> {
         Ok(())
     }
 
-    fn apply_ark<CS: ConstraintSystem<F>, FG : FieldGadget<F, F>>(&self, mut cs: CS, state: &mut[FG], round_number: usize)
+    fn apply_ark<CS: ConstraintSystem<F>, FG : FieldGadget<F, F>>(&self, mut cs: CS, state: &mut[FG], round_number: usize) -> Result<(), SynthesisError>
     {
         for i in 0..state.len()
         {
-            state[i].add_constant_in_place(&mut cs, &self.ark[round_number][i]);
+            state[i].add_constant_in_place(&mut cs, &self.ark[round_number][i])?;
         }
+        Ok(())
     }
 
     fn apply_mds<CS: ConstraintSystem<F>, FG : FieldGadget<F, F>>(&self, mut cs: CS, state: &mut[FG]) -> Result<(), SynthesisError>
@@ -74,7 +75,7 @@ impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for PoseidonPermut
         for i in 0..full_rounds_over_2
         {
             let mut cs_i = cs.ns(|| format!("Poseidon round {:?}", i));
-            self.apply_ark(cs_i.ns(|| "ark"), state, i as usize);
+            self.apply_ark(cs_i.ns(|| "ark"), state, i as usize)?;
             self.apply_s_box(cs_i.ns(|| "s_box"), state, true)?;
             self.apply_mds(cs_i.ns(|| "mds"), state)?;
         }
@@ -83,7 +84,7 @@ impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for PoseidonPermut
         {
             let mut cs_i = cs.ns(|| format!("Poseidon round {:?}", i));
             // TODO: Optimize out most of the ARK / MDS work in partial rounds
-            self.apply_ark(cs_i.ns(|| "ark"), state, i as usize);
+            self.apply_ark(cs_i.ns(|| "ark"), state, i as usize)?;
             self.apply_s_box(cs_i.ns(|| "s_box"), state, false)?;
             self.apply_mds(cs_i.ns(|| "mds"), state)?;
         }
@@ -91,7 +92,7 @@ impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for PoseidonPermut
         for i in (full_rounds_over_2 + self.partial_rounds)..(self.partial_rounds + self.full_rounds)
         {
             let mut cs_i = cs.ns(|| format!("Poseidon round {:?}", i));
-            self.apply_ark(cs_i.ns(|| "ark"), state, i as usize);
+            self.apply_ark(cs_i.ns(|| "ark"), state, i as usize)?;
             self.apply_s_box(cs_i.ns(|| "s_box"), state, true)?;
             self.apply_mds(cs_i.ns(|| "mds"), state)?;
         }
@@ -361,10 +362,10 @@ mod test {
         let mut cs = TestConstraintSystem::<Fr>::new();
         let poseidon = libiop_near_mds_high_alpha_poseidon::<Fr>();
         let mut state = vec![FrGadget::zero(&mut cs)?, FrGadget::zero(&mut cs)?, FrGadget::zero(&mut cs)?];
-        poseidon.permute(&mut cs, &mut state);
+        poseidon.permute(&mut cs, &mut state)?;
         let expected = Fr::from_str("19745903574422741006139475519330790957027605504238596103618584028691101830733").map_err(|_| ()).unwrap();
         let exp_gadg = FrGadget::alloc(&mut cs, || Ok(expected))?;
-        state[0].enforce_equal(&mut cs, &exp_gadg);
+        state[0].enforce_equal(&mut cs, &exp_gadg)?;
         assert!(cs.is_satisfied());
         Ok(())
     }
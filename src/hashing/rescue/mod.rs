@@ -38,14 +38,13 @@ impl<F: Field> RescuePermutation<F> {
         for i in 0..state.len()
         {
             // first compute val = x^{1/alpha}
-            let mut val = state[i].get_value().unwrap();
-            val = val.pow(&self.one_over_alpha);
+            let val = state[i].get_value().map(|x| x.pow(&self.one_over_alpha));
             // Due to lack of back-end optimizations, we do the non-determinism in a slightly weird manner.
             // we allocate val, and then exponentiate that value to alpha-1. This yields x^{alpha - 1 / alpha}.
             // Since alpha must be odd, this has not introduced any additional multiplications. Finally we check that 
             // that exponentiated value, times val = state[i]. 
             // Finally update state[i]
-            let new_state = FG::alloc(&mut cs.ns(|| format!("alloc new state elem {:?}", i)), || Ok(val))?;
+            let new_state = FG::alloc(&mut cs.ns(|| format!("alloc new state elem {:?}", i)), || val.ok_or(SynthesisError::AssignmentMissing))?;
             let new_state_to_alpha_minus_one = new_state.pow_by_constant(
                 &mut cs.ns(|| format!("compute x^(alpha - 1 over alpha) for state elem {:?}", i)),
                 &[self.alpha - 1])?;
@@ -61,12 +60,13 @@ impl<F: Field> RescuePermutation<F> {
         Ok(())
     }
 
-    fn apply_ark<CS: ConstraintSystem<F>, FG : FieldGadget<F, F>>(&self, mut cs: CS, state: &mut[FG], round_number: usize)
+    fn apply_ark<CS: ConstraintSystem<F>, FG : FieldGadget<F, F>>(&self, mut cs: CS, state: &mut[FG], round_number: usize) -> Result<(), SynthesisError>
     {
         for i in 0..state.len()
         {
-            state[i].add_constant_in_place(&mut cs, &self.ark[round_number][i]);
+            state[i].add_constant_in_place(&mut cs, &self.ark[round_number][i])?;
         }
+        Ok(())
     }
 
     fn apply_mds<CS: ConstraintSystem<F>, FG : FieldGadget<F, F>>(&self, mut cs: CS, state: &mut[FG]) -> Result<(), SynthesisError>
@@ -102,7 +102,7 @@ impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for RescuePermutat
                 let mut cs_alpha = cs.ns(|| format!("Rescue round {:?}, alpha step", i));
                 self.apply_alpha_s_box(cs_alpha.ns(|| "s_box"), state)?;
                 self.apply_mds(cs_alpha.ns(|| "mds"), state)?;
-                self.apply_ark(cs_alpha.ns(|| "ark"), state, step_num);
+                self.apply_ark(cs_alpha.ns(|| "ark"), state, step_num)?;
                 step_num += 1;
             }
 
@@ -111,7 +111,7 @@ impl<F: Field, FG: FieldGadget<F,F>> PermutationGadget<F, FG> for RescuePermutat
                 let mut cs_one_over_alpha = cs.ns(|| format!("Rescue round {:?}, one over alpha step", i));
                 self.apply_one_over_alpha_s_box(cs_one_over_alpha.ns(|| "s_box"), state)?;
                 self.apply_mds(cs_one_over_alpha.ns(|| "mds"), state)?;
-                self.apply_ark(cs_one_over_alpha.ns(|| "ark"), state, step_num);
+                self.apply_ark(cs_one_over_alpha.ns(|| "ark"), state, step_num)?;
                 step_num += 1;
             }
         }
@@ -1,16 +1,39 @@
-use algebra::prelude::*;
+use algebra::{prelude::*, FpParameters};
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{prelude::*, fields::fp::FpGadget};
 
 use crate::hashing::*;
+use crate::Error;
 use num_traits::*;
 
-#[derive(Clone)]
-enum SpongeState {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpongeState {
     Absorbing{next_absorb_index : usize},
     Squeezing{next_squeeze_index : usize},
 }
 
+/// How absorbed elements are combined with the rate.
+/// Add mode adds each element into the rate, Overwrite mode replaces the rate element with it.
+/// In-circuit, Overwrite mode needs no addition constraints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbsorbMode {
+    Add,
+    Overwrite,
+}
+
+/// A checkpoint of a sponge, from which an identical sponge can be rebuilt with `from_state`.
+/// T is F for the native sponge, and FG for the sponge gadget.
+#[derive(Clone)]
+pub struct SpongeSnapshot<F: Clone, T: Clone>
+{
+    pub state: Vec<T>,
+    pub mode: SpongeState,
+    pub absorb_mode: AbsorbMode,
+    pub capacity_iv: Vec<F>,
+    // The gadget's count of permutations, which keeps its constraint system namespaces unique. Always 0 natively.
+    pub permute_index: usize,
+}
+
 #[derive(Clone)]
 pub struct AlgebraicSponge<F: PrimeField, P: Permutation<F>>
 {
@@ -19,6 +42,9 @@ pub struct AlgebraicSponge<F: PrimeField, P: Permutation<F>>
     capacity: usize,
     permutation: P,
     mode: SpongeState,
+    absorb_mode: AbsorbMode,
+    // Constants the capacity is initialized to, for domain separation.
+    capacity_iv: Vec<F>,
 }
 
 impl<F: PrimeField, P: Permutation<F>> AlgebraicSponge<F, P>
@@ -34,12 +60,100 @@ impl<F: PrimeField, P: Permutation<F>> AlgebraicSponge<F, P>
             capacity,
             permutation,
             mode,
+            absorb_mode: AbsorbMode::Add,
+            capacity_iv: Vec::new(),
+        }
+    }
+
+    /// Sets how absorbed elements are combined with the rate. Sponges default to AbsorbMode::Add.
+    pub fn with_absorb_mode(mut self, absorb_mode: AbsorbMode) -> Self
+    {
+        self.absorb_mode = absorb_mode;
+        self
+    }
+
+    /// Sets the capacity to the given constants, both now and whenever the sponge is reset.
+    /// Sponges with different IVs are domain separated from one another.
+    pub fn with_capacity_iv(mut self, capacity_iv: Vec<F>) -> Result<Self, Error>
+    {
+        if capacity_iv.len() > self.capacity
+        {
+            return Err(Error::CapacityTooSmall{capacity: self.capacity, needed: capacity_iv.len()});
         }
+        self.capacity_iv = capacity_iv;
+        self.reset();
+        Ok(self)
     }
 
     pub fn reset(&mut self)
     {
         self.state = vec![F::zero(); self.rate + self.capacity];
+        for i in 0..self.capacity_iv.len()
+        {
+            self.state[self.rate + i] = self.capacity_iv[i];
+        }
+        self.mode = SpongeState::Absorbing{ next_absorb_index: 0};
+    }
+
+    /// Returns a snapshot of the full sponge, including the absorb / squeeze position, absorb mode and capacity IV.
+    pub fn state(&self) -> SpongeSnapshot<F, F>
+    {
+        SpongeSnapshot{
+            state: self.state.clone(),
+            mode: self.mode.clone(),
+            absorb_mode: self.absorb_mode,
+            capacity_iv: self.capacity_iv.clone(),
+            permute_index: 0,
+        }
+    }
+
+    /// Rebuilds a sponge from a snapshot taken with `state`.
+    pub fn from_state(rate: usize, capacity: usize, permutation: P, snapshot: SpongeSnapshot<F, F>) -> Result<Self, Error>
+    {
+        check_snapshot(rate, capacity, &snapshot)?;
+        Ok(AlgebraicSponge{
+            state: snapshot.state,
+            rate,
+            capacity,
+            permutation,
+            mode: snapshot.mode,
+            absorb_mode: snapshot.absorb_mode,
+            capacity_iv: snapshot.capacity_iv,
+        })
+    }
+
+    /// Derives a child sponge that is domain separated from this one, and from forks with other labels.
+    /// The label is added into the last capacity element and the child is permuted, so none of its outputs
+    /// can be predicted from the parent's. The parent is left unchanged.
+    /// The label must be non-zero, as otherwise the child would continue exactly like the parent.
+    /// The capacity IV must leave the last capacity element free, so that a label can't cancel out the IV.
+    pub fn fork(&self, label: F) -> Result<Self, Error>
+        where P: Clone
+    {
+        if label.is_zero()
+        {
+            return Err(Error::ZeroForkLabel);
+        }
+        if self.capacity_iv.len() >= self.capacity
+        {
+            return Err(Error::CapacityTooSmall{capacity: self.capacity, needed: self.capacity_iv.len() + 1});
+        }
+        let mut child = self.clone();
+        child.state[child.rate + child.capacity - 1] += label;
+        child.permute();
+        child.mode = SpongeState::Absorbing{ next_absorb_index: 0};
+        Ok(child)
+    }
+
+    /// Permutes, and then zeroes the rate.
+    /// After ratcheting, the previous state cannot be recovered even if the current state leaks.
+    pub fn ratchet(&mut self)
+    {
+        self.permute();
+        for i in 0..self.rate
+        {
+            self.state[i] = F::zero();
+        }
         self.mode = SpongeState::Absorbing{ next_absorb_index: 0};
     }
 
@@ -60,8 +174,10 @@ impl<F: PrimeField, P: Permutation<F>> AlgebraicSponge<F, P>
                 }
                 self.absorb_internal(absorb_index, elements);
             },
+            // We are in a duplex sponge construction. 
+            // It is safe to squeeze the rate and absorb the new input without permuting.
+            // This must match AlgebraicSpongeGadget::absorb, so that native and in-circuit outputs agree.
             SpongeState::Squeezing{ next_squeeze_index } => { 
-                self.permute(); 
                 self.absorb_internal(0, elements);
             },
         };
@@ -75,7 +191,7 @@ impl<F: PrimeField, P: Permutation<F>> AlgebraicSponge<F, P>
         {
             for i in 0..elements.len()
             {
-                self.state[i + rate_start_index] += elements[i];
+                self.absorb_elem(i + rate_start_index, elements[i]);
             }
             self.mode = SpongeState::Absorbing{ next_absorb_index: rate_start_index + elements.len() };
             return;
@@ -84,13 +200,21 @@ impl<F: PrimeField, P: Permutation<F>> AlgebraicSponge<F, P>
         let num_elements_absorbed = self.rate - rate_start_index;
         for i in 0..num_elements_absorbed
         {
-            self.state[i + rate_start_index] += elements[i];
+            self.absorb_elem(i + rate_start_index, elements[i]);
         }
         self.permute();
         // Tail recurse, with the input elements being truncated by num elements absorbed
         self.absorb_internal(0, &elements[num_elements_absorbed..]);
     }
 
+    fn absorb_elem(&mut self, state_index: usize, element: F)
+    {
+        match self.absorb_mode {
+            AbsorbMode::Add => self.state[state_index] += element,
+            AbsorbMode::Overwrite => self.state[state_index] = element,
+        }
+    }
+
     pub fn squeeze(&mut self, num_elements: usize) -> Vec<F>
     {
         let mut squeezed_elems = vec![F::zero(); num_elements];
@@ -141,6 +265,152 @@ impl<F: PrimeField, P: Permutation<F>> AlgebraicSponge<F, P>
         // Tail recurse, with the correct change to indices in output happening due to changing the slice
         self.squeeze_internal(0, &mut output[num_elements_squeezed..]);
     }
+
+    /// Squeezes `num_bits` bits out of the sponge, in little-endian order.
+    /// Each squeezed field element contributes only its `bits_per_elem::<F>()` least significant bits,
+    /// and elements are concatenated in the order they are squeezed.
+    pub fn squeeze_bits(&mut self, num_bits: usize) -> Vec<bool>
+    {
+        if num_bits == 0
+        {
+            return Vec::new();
+        }
+        let bits_per_elem = bits_per_elem::<F>();
+        let num_elements = (num_bits + bits_per_elem - 1) / bits_per_elem;
+        let squeezed_elems = self.squeeze(num_elements);
+
+        let mut bits = Vec::with_capacity(num_elements * bits_per_elem);
+        for elem in squeezed_elems
+        {
+            bits.extend(to_le_bits(&elem, bits_per_elem));
+        }
+        bits.truncate(num_bits);
+        bits
+    }
+
+    /// Squeezes `num_bytes` bytes out of the sponge.
+    /// Byte i is built from bits [8i, 8i + 8) of `squeeze_bits`, least significant bit first.
+    pub fn squeeze_bytes(&mut self, num_bytes: usize) -> Vec<u8>
+    {
+        let bits = self.squeeze_bits(8 * num_bytes);
+        bits.chunks(8).map(|byte_bits| le_bits_to_u64(byte_bits) as u8).collect()
+    }
+
+    /// Squeezes `num_elements` integers of `bits_per_int` bits each.
+    /// Each integer consumes one squeezed field element, and is that element's
+    /// `bits_per_int` least significant bits.
+    pub fn squeeze_ints(&mut self, num_elements: usize, bits_per_int: usize) -> Result<Vec<u64>, Error>
+    {
+        check_bits_per_int::<F>(bits_per_int)?;
+        if num_elements == 0
+        {
+            return Ok(Vec::new());
+        }
+        let squeezed_elems = self.squeeze(num_elements);
+        Ok(squeezed_elems.iter().map(|elem| le_bits_to_u64(&to_le_bits(elem, bits_per_int))).collect())
+    }
+
+    /// Squeezes `count` indices of `bits_each` bits each, packing as many indices as fit into
+    /// the `bits_per_elem::<F>()` usable bits of every squeezed field element.
+    /// Index j of an element is bits [j * bits_each, (j + 1) * bits_each) of that element, read little-endian.
+    /// Indices never straddle two field elements.
+    pub fn squeeze_indices(&mut self, count: usize, bits_each: usize) -> Result<Vec<u64>, Error>
+    {
+        let indices_per_elem = indices_per_elem::<F>(bits_each)?;
+        if count == 0
+        {
+            return Ok(Vec::new());
+        }
+        let num_elements = (count + indices_per_elem - 1) / indices_per_elem;
+        let squeezed_elems = self.squeeze(num_elements);
+
+        let mut indices = Vec::with_capacity(count);
+        for elem in squeezed_elems
+        {
+            let bits = to_le_bits(&elem, indices_per_elem * bits_each);
+            for index_bits in bits.chunks(bits_each)
+            {
+                indices.push(le_bits_to_u64(index_bits));
+            }
+        }
+        indices.truncate(count);
+        Ok(indices)
+    }
+}
+
+/// The statistical security parameter λ of squeezed bits.
+pub const SQUEEZE_SECURITY_BITS: usize = 128;
+
+/// The number of bits taken from each squeezed field element, which is `CAPACITY - SQUEEZE_SECURITY_BITS`.
+/// The k least significant bits of a uniform element of F are at statistical distance at most 2^k / p from uniform,
+/// and p > 2^CAPACITY, so the bits of each element are within 2^-λ of uniform.
+/// Taking all `CAPACITY` bits would leave the top bit noticeably biased, e.g. it is one with probability ≈ 0.34 on BN254.
+/// Fields with a capacity of at most λ bits are not supported.
+pub fn bits_per_elem<F: PrimeField>() -> usize
+{
+    (F::Params::CAPACITY as usize).checked_sub(SQUEEZE_SECURITY_BITS)
+        .filter(|bits| *bits > 0)
+        .expect("the field capacity must exceed SQUEEZE_SECURITY_BITS")
+}
+
+/// The number of `bits_each`-bit indices that `squeeze_indices` packs into a single field element.
+pub fn indices_per_elem<F: PrimeField>(bits_each: usize) -> Result<usize, Error>
+{
+    if bits_each == 0
+    {
+        return Err(Error::InvalidBitWidth{bits: bits_each, max: max_bits_per_int::<F>()});
+    }
+    check_bits_per_int::<F>(bits_each)?;
+    Ok(bits_per_elem::<F>() / bits_each)
+}
+
+// Squeezed integers are returned as u64s, and are taken from the usable bits of a single field element.
+fn max_bits_per_int<F: PrimeField>() -> usize
+{
+    std::cmp::min(64, bits_per_elem::<F>())
+}
+
+fn check_bits_per_int<F: PrimeField>(bits_per_int: usize) -> Result<(), Error>
+{
+    if bits_per_int > max_bits_per_int::<F>()
+    {
+        return Err(Error::InvalidBitWidth{bits: bits_per_int, max: max_bits_per_int::<F>()});
+    }
+    Ok(())
+}
+
+fn check_snapshot<F: Clone, T: Clone>(rate: usize, capacity: usize, snapshot: &SpongeSnapshot<F, T>) -> Result<(), Error>
+{
+    if snapshot.state.len() != rate + capacity
+    {
+        return Err(Error::InvalidLength{expected: rate + capacity, found: snapshot.state.len()});
+    }
+    if snapshot.capacity_iv.len() > capacity
+    {
+        return Err(Error::CapacityTooSmall{capacity, needed: snapshot.capacity_iv.len()});
+    }
+    Ok(())
+}
+
+// Returns the `num_bits` least significant bits of elem, in little-endian order.
+fn to_le_bits<F: PrimeField>(elem: &F, num_bits: usize) -> Vec<bool>
+{
+    // BigInteger::to_bits is big-endian
+    let mut bits = elem.into_repr().to_bits();
+    bits.reverse();
+    bits.truncate(num_bits);
+    bits
+}
+
+fn le_bits_to_u64(bits: &[bool]) -> u64
+{
+    let mut res = 0;
+    for bit in bits.iter().rev()
+    {
+        res <<= 1;
+        res += *bit as u64;
+    }
+    res
 }
 
 #[derive(Clone)]
@@ -151,6 +421,8 @@ pub struct AlgebraicSpongeGadget<F: PrimeField, FG: FieldGadget<F, F>, P: Permut
     capacity: usize,
     permutation: P,
     mode: SpongeState,
+    absorb_mode: AbsorbMode,
+    capacity_iv: Vec<F>,
 
     // needed for cs unique prefixes
     global_permute_index: usize,
@@ -181,14 +453,100 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG>> Algebrai
             capacity,
             permutation,
             mode,
+            absorb_mode: AbsorbMode::Add,
+            capacity_iv: Vec::new(),
             global_permute_index: 0,
             _phantom: F::zero(),
         })
     }
 
+    /// See `AlgebraicSponge::with_absorb_mode`
+    pub fn with_absorb_mode(mut self, absorb_mode: AbsorbMode) -> Self
+    {
+        self.absorb_mode = absorb_mode;
+        self
+    }
+
+    /// See `AlgebraicSponge::with_capacity_iv`. The IV is a constant, so setting it is free.
+    pub fn with_capacity_iv<CS: ConstraintSystem<F>>(mut self, mut cs: CS, capacity_iv: Vec<F>) -> Result<Self, Error>
+    {
+        if capacity_iv.len() > self.capacity
+        {
+            return Err(Error::CapacityTooSmall{capacity: self.capacity, needed: capacity_iv.len()});
+        }
+        self.capacity_iv = capacity_iv;
+        self.reset(&mut cs)?;
+        Ok(self)
+    }
+
     pub fn reset<CS: ConstraintSystem<F>>(&mut self, mut cs: CS) -> Result<(), SynthesisError>
     {
         self.state = AlgebraicSpongeGadget::<F, FG, P>::zero_state(&mut cs, self.rate + self.capacity)?;
+        for i in 0..self.capacity_iv.len()
+        {
+            self.state[self.rate + i].add_constant_in_place(&mut cs.ns(|| format!("capacity iv {:?}", i)), &self.capacity_iv[i])?;
+        }
+        self.mode = SpongeState::Absorbing{ next_absorb_index: 0};
+        Ok(())
+    }
+
+    /// See `AlgebraicSponge::state`
+    pub fn state(&self) -> SpongeSnapshot<F, FG>
+    {
+        SpongeSnapshot{
+            state: self.state.clone(),
+            mode: self.mode.clone(),
+            absorb_mode: self.absorb_mode,
+            capacity_iv: self.capacity_iv.clone(),
+            permute_index: self.global_permute_index,
+        }
+    }
+
+    /// See `AlgebraicSponge::from_state`
+    pub fn from_state(rate: usize, capacity: usize, permutation: P, snapshot: SpongeSnapshot<F, FG>) -> Result<Self, Error>
+    {
+        check_snapshot(rate, capacity, &snapshot)?;
+        Ok(AlgebraicSpongeGadget{
+            state: snapshot.state,
+            rate,
+            capacity,
+            permutation,
+            mode: snapshot.mode,
+            absorb_mode: snapshot.absorb_mode,
+            capacity_iv: snapshot.capacity_iv,
+            global_permute_index: snapshot.permute_index,
+            _phantom: F::zero(),
+        })
+    }
+
+    /// See `AlgebraicSponge::fork`. The label is a constant, so adding it is free.
+    pub fn fork<CS: ConstraintSystem<F>>(&self, mut cs: CS, label: F) -> Result<Self, Error>
+        where P: Clone
+    {
+        if label.is_zero()
+        {
+            return Err(Error::ZeroForkLabel);
+        }
+        if self.capacity_iv.len() >= self.capacity
+        {
+            return Err(Error::CapacityTooSmall{capacity: self.capacity, needed: self.capacity_iv.len() + 1});
+        }
+        let mut child = self.clone();
+        let fork_index = child.rate + child.capacity - 1;
+        child.state[fork_index].add_constant_in_place(&mut cs.ns(|| "add fork label"), &label)?;
+        child.permute(&mut cs.ns(|| "fork"))?;
+        child.mode = SpongeState::Absorbing{ next_absorb_index: 0};
+        Ok(child)
+    }
+
+    /// See `AlgebraicSponge::ratchet`
+    pub fn ratchet<CS: ConstraintSystem<F>>(&mut self, mut cs: CS) -> Result<(), SynthesisError>
+    {
+        self.permute(&mut cs.ns(|| "ratchet"))?;
+        for i in 0..self.rate
+        {
+            self.state[i] = FG::zero(&mut cs.ns(|| format!("zero rate elem {:?}", i)))?;
+        }
         self.mode = SpongeState::Absorbing{ next_absorb_index: 0};
         Ok(())
     }
@@ -213,7 +571,8 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG>> Algebrai
                 self.absorb_internal(&mut cs, absorb_index, elements)?;
             },
             // We are in a duplex sponge construction. 
-            // It is safe to squeeze the rate and absorb the new input without permuting 
+            // It is safe to squeeze the rate and absorb the new input without permuting.
+            // This must match AlgebraicSponge::absorb.
             SpongeState::Squeezing{ next_squeeze_index } => { 
                 self.absorb_internal(&mut cs, 0, elements)?;
             },
@@ -229,7 +588,7 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG>> Algebrai
         {
             for i in 0..elements.len()
             {
-                self.state[i + rate_start_index].add_in_place(&mut cs, &elements[i])?;
+                self.absorb_elem(&mut cs.ns(|| format!("absorb elem {:?}", i)), i + rate_start_index, &elements[i])?;
             }
             self.mode = SpongeState::Absorbing{ next_absorb_index: rate_start_index + elements.len() };
             return Ok(());
@@ -238,13 +597,22 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG>> Algebrai
         let num_elements_absorbed = self.rate - rate_start_index;
         for i in 0..num_elements_absorbed
         {
-            self.state[i + rate_start_index].add_in_place(&mut cs, &elements[i])?;
+            self.absorb_elem(&mut cs.ns(|| format!("absorb elem {:?}", i)), i + rate_start_index, &elements[i])?;
         }
         self.permute(&mut cs)?;
         // Tail recurse, with the input elements being truncated by num elements absorbed
         self.absorb_internal(cs, 0, &elements[num_elements_absorbed..])
     }
 
+    fn absorb_elem<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, state_index: usize, element: &FG) -> Result<(), SynthesisError>
+    {
+        match self.absorb_mode {
+            AbsorbMode::Add => { self.state[state_index].add_in_place(&mut cs, element)?; },
+            AbsorbMode::Overwrite => { self.state[state_index] = element.clone(); },
+        };
+        Ok(())
+    }
+
     pub fn squeeze<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_elements: usize) -> Result<Vec<FG>, SynthesisError>
     {
         let mut squeezed_elems = AlgebraicSpongeGadget::<F, FG, P>::zero_state(&mut cs, num_elements)?;
@@ -295,4 +663,286 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG>> Algebrai
         // Tail recurse, with the correct change to indices in output happening due to changing the slice
         self.squeeze_internal(cs, 0, &mut output[num_elements_squeezed..])
     }
-}
\ No newline at end of file
+
+    /// Squeezes `num_bits` bits out of the sponge, in little-endian order.
+    /// This matches `AlgebraicSponge::squeeze_bits` exactly.
+    pub fn squeeze_bits<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_bits: usize) -> Result<Vec<Boolean>, SynthesisError>
+    {
+        if num_bits == 0
+        {
+            return Ok(Vec::new());
+        }
+        let bits_per_elem = bits_per_elem::<F>();
+        let num_elements = (num_bits + bits_per_elem - 1) / bits_per_elem;
+        let squeezed_elems = self.squeeze(&mut cs.ns(|| "squeeze bits"), num_elements)?;
+
+        let mut bits = Vec::with_capacity(num_elements * bits_per_elem);
+        for i in 0..squeezed_elems.len()
+        {
+            let elem_bits = Self::to_le_bits(
+                &mut cs.ns(|| format!("bits of squeezed elem {:?}", i)), &squeezed_elems[i], bits_per_elem)?;
+            bits.extend(elem_bits);
+        }
+        bits.truncate(num_bits);
+        Ok(bits)
+    }
+
+    /// Squeezes `num_bytes` bytes out of the sponge.
+    /// This matches `AlgebraicSponge::squeeze_bytes` exactly.
+    pub fn squeeze_bytes<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_bytes: usize) -> Result<Vec<UInt8>, SynthesisError>
+    {
+        let bits = self.squeeze_bits(&mut cs, 8 * num_bytes)?;
+        Ok(bits.chunks(8).map(UInt8::from_bits_le).collect())
+    }
+
+    /// Squeezes `num_elements` integers of `bits_per_int` bits each, using one squeezed field element per integer.
+    /// The integers match `AlgebraicSponge::squeeze_ints`, but each one is returned as its bits in big-endian order,
+    /// which is the order `mux` and `MerklePathGadget` consume.
+    pub fn squeeze_ints<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, num_elements: usize, bits_per_int: usize) -> Result<Vec<Vec<Boolean>>, Error>
+    {
+        check_bits_per_int::<F>(bits_per_int)?;
+        if num_elements == 0
+        {
+            return Ok(Vec::new());
+        }
+        let squeezed_elems = self.squeeze(&mut cs.ns(|| "squeeze ints"), num_elements)?;
+
+        let mut ints = Vec::with_capacity(num_elements);
+        for i in 0..squeezed_elems.len()
+        {
+            let mut int_bits = Self::to_le_bits(
+                &mut cs.ns(|| format!("bits of squeezed elem {:?}", i)), &squeezed_elems[i], bits_per_int)?;
+            int_bits.reverse();
+            ints.push(int_bits);
+        }
+        Ok(ints)
+    }
+
+    /// Squeezes `count` indices of `bits_each` bits each, packing several indices into every squeezed field element.
+    /// The indices match `AlgebraicSponge::squeeze_indices`, but each one is returned as its bits in big-endian order,
+    /// as in `squeeze_ints`. Only one bit decomposition is done per squeezed field element.
+    pub fn squeeze_indices<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, count: usize, bits_each: usize) -> Result<Vec<Vec<Boolean>>, Error>
+    {
+        let indices_per_elem = indices_per_elem::<F>(bits_each)?;
+        if count == 0
+        {
+            return Ok(Vec::new());
+        }
+        let num_elements = (count + indices_per_elem - 1) / indices_per_elem;
+        let squeezed_elems = self.squeeze(&mut cs.ns(|| "squeeze indices"), num_elements)?;
+
+        let mut indices = Vec::with_capacity(count);
+        for i in 0..squeezed_elems.len()
+        {
+            let bits = Self::to_le_bits(
+                &mut cs.ns(|| format!("bits of squeezed elem {:?}", i)), &squeezed_elems[i], indices_per_elem * bits_each)?;
+            for index_bits in bits.chunks(bits_each)
+            {
+                let mut index_bits = index_bits.to_vec();
+                index_bits.reverse();
+                indices.push(index_bits);
+            }
+        }
+        indices.truncate(count);
+        Ok(indices)
+    }
+
+    // Returns the `num_bits` least significant bits of elem, in little-endian order.
+    // The decomposition is strict, as otherwise a malicious prover could bias the squeezed bits
+    // by choosing a non-canonical decomposition.
+    fn to_le_bits<CS: ConstraintSystem<F>>(mut cs: CS, elem: &FG, num_bits: usize) -> Result<Vec<Boolean>, SynthesisError>
+    {
+        // to_bits_strict is big-endian
+        let mut bits = elem.to_bits_strict(&mut cs)?;
+        bits.reverse();
+        bits.truncate(num_bits);
+        Ok(bits)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::{ConstraintSystem, SynthesisError};
+    use algebra::prelude::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::hashing::{Permutation, dummy_permutation::*, sponge::*};
+    use crate::Error;
+
+    fn values(elems: &[FrGadget]) -> Vec<Fr>
+    {
+        elems.iter().map(|x| x.get_value().unwrap()).collect()
+    }
+
+    #[test]
+    fn sponge_snapshot_test() -> Result<(), Error> {
+        let P = SeededDummyPermutation{seed: Fr::from(3u32)};
+        let mut sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(2, 1, P.clone())
+            .with_absorb_mode(AbsorbMode::Overwrite)
+            .with_capacity_iv(vec![Fr::from(4u32)])?;
+        sponge.absorb(&[Fr::from(1u32)]);
+        let snapshot = sponge.state();
+        // A snapshot only fits a sponge of the same width
+        assert!(AlgebraicSponge::from_state(3, 1, P.clone(), snapshot.clone()).is_err());
+        let mut restored = AlgebraicSponge::from_state(2, 1, P, snapshot)?;
+        // The absorb index and absorb mode are part of the snapshot
+        sponge.absorb(&[Fr::from(2u32), Fr::from(5u32)]);
+        restored.absorb(&[Fr::from(2u32), Fr::from(5u32)]);
+        assert_eq!(sponge.squeeze(3), restored.squeeze(3));
+        // So is the capacity IV, which reset restores
+        sponge.reset();
+        restored.reset();
+        assert_eq!(sponge.squeeze(3), restored.squeeze(3));
+        Ok(())
+    }
+
+    #[test]
+    fn sponge_fork_test() -> Result<(), Error> {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let mut sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P);
+        sponge.absorb(&[Fr::from(1u32)]);
+        let mut child_1 = sponge.fork(Fr::from(1u32))?;
+        let mut child_1_again = sponge.fork(Fr::from(1u32))?;
+        let mut child_2 = sponge.fork(Fr::from(2u32))?;
+
+        let parent_out = sponge.clone().squeeze(2);
+        let child_1_out = child_1.squeeze(2);
+        assert_eq!(child_1_out, child_1_again.squeeze(2));
+        assert_ne!(child_1_out, child_2.squeeze(2));
+        assert_ne!(child_1_out, parent_out);
+        // Forking does not change the parent
+        assert_eq!(sponge.squeeze(2), parent_out);
+        Ok(())
+    }
+
+    #[test]
+    fn sponge_fork_capacity_iv_test() -> Result<(), Error> {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        // The label goes in the last capacity element, apart from the IV
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 2, P.clone())
+            .with_capacity_iv(vec![Fr::from(5u32)])?;
+        let mut expected = sponge.state().state;
+        expected[3] += Fr::from(7u32);
+        Permutation::permute(&P, &mut expected);
+        assert_eq!(sponge.fork(Fr::from(7u32))?.state().state, expected);
+
+        // An IV filling the whole capacity leaves no room for a label
+        let full_iv_sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P.clone())
+            .with_capacity_iv(vec![Fr::from(5u32)])?;
+        assert!(full_iv_sponge.fork(Fr::from(7u32)).is_err());
+        // Nor can an IV be longer than the capacity
+        assert!(AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P)
+            .with_capacity_iv(vec![Fr::from(5u32), Fr::from(6u32)]).is_err());
+        assert!(sponge.fork(Fr::zero()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sponge_ratchet_test() {
+        let P = SeededDummyPermutation{seed: Fr::from(3u32)};
+        let mut sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(2, 1, P);
+        sponge.absorb(&[Fr::from(1u32), Fr::from(2u32)]);
+        sponge.ratchet();
+        let snapshot = sponge.state();
+        assert_eq!(snapshot.state[0], Fr::zero());
+        assert_eq!(snapshot.state[1], Fr::zero());
+        assert_eq!(snapshot.mode, SpongeState::Absorbing{ next_absorb_index: 0});
+    }
+
+    #[test]
+    fn sponge_fork_ratchet_gadget_consistency_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let mut sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P.clone());
+        let mut sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, P.clone())?;
+
+        let input = FrGadget::alloc(&mut cs.ns(|| "input"), || Ok(Fr::from(7u32)))?;
+        sponge.absorb(&[Fr::from(7u32)]);
+        sponge_gadget.absorb(&mut cs.ns(|| "absorb"), &[input])?;
+
+        let mut child = sponge.fork(Fr::from(9u32))?;
+        let mut child_gadget = sponge_gadget.fork(&mut cs.ns(|| "fork"), Fr::from(9u32))?;
+        assert_eq!(child.squeeze(3), values(&child_gadget.squeeze(&mut cs.ns(|| "child squeeze"), 3)?));
+
+        sponge.ratchet();
+        sponge_gadget.ratchet(&mut cs.ns(|| "ratchet"))?;
+        let mut restored = AlgebraicSpongeGadget::from_state(2, 1, P, sponge_gadget.state())?;
+        assert_eq!(sponge.squeeze(3), values(&restored.squeeze(&mut cs.ns(|| "restored squeeze"), 3)?));
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    // Absorbing after a squeeze continues the duplex without an extra permutation, natively and in-circuit.
+    #[test]
+    fn absorb_after_squeeze_test() -> Result<(), SynthesisError> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let P = SeededDummyPermutation{seed: Fr::one()};
+        let mut sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(2, 1, P.clone());
+        let mut sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, SeededDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, P)?;
+        let inputs: Vec<Fr> = vec![5u32, 6].into_iter().map(Fr::from).collect();
+        let mut input_gadgets = Vec::new();
+        for i in 0..2 {
+            input_gadgets.push(FrGadget::alloc(&mut cs.ns(|| format!("input {:?}", i)), || Ok(inputs[i]))?);
+        }
+
+        // [5, 0, 0] -> permute -> [6, 2, 3]
+        sponge.absorb(&inputs[0..1]);
+        assert_eq!(sponge.squeeze(1), vec![Fr::from(6u32)]);
+        // [12, 2, 3] -> permute -> [13, 4, 6]
+        sponge.absorb(&inputs[1..]);
+        assert_eq!(sponge.squeeze(2), vec![Fr::from(13u32), Fr::from(4u32)]);
+
+        sponge_gadget.absorb(&mut cs.ns(|| "absorb 1"), &input_gadgets[0..1])?;
+        let out_1 = sponge_gadget.squeeze(&mut cs.ns(|| "squeeze 1"), 1)?;
+        sponge_gadget.absorb(&mut cs.ns(|| "absorb 2"), &input_gadgets[1..])?;
+        let out_2 = sponge_gadget.squeeze(&mut cs.ns(|| "squeeze 2"), 2)?;
+        assert_eq!(values(&out_1), vec![Fr::from(6u32)]);
+        assert_eq!(values(&out_2), vec![Fr::from(13u32), Fr::from(4u32)]);
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn overwrite_mode_test() -> Result<(), SynthesisError> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let P = SeededDummyPermutation{seed: Fr::one()};
+        let mut add_sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(2, 1, P.clone());
+        let mut overwrite_sponge = AlgebraicSponge::<Fr, SeededDummyPermutation<Fr>>::new(2, 1, P.clone())
+            .with_absorb_mode(AbsorbMode::Overwrite);
+        let mut overwrite_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, SeededDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, P)?.with_absorb_mode(AbsorbMode::Overwrite);
+
+        let inputs: Vec<Fr> = vec![5u32, 6, 7].into_iter().map(Fr::from).collect();
+        let mut input_gadgets = Vec::new();
+        for i in 0..3 {
+            input_gadgets.push(FrGadget::alloc(&mut cs.ns(|| format!("input {:?}", i)), || Ok(inputs[i]))?);
+        }
+
+        // [5, 6, 0] -> permute -> [6, 8, 3] -> overwrite 7 -> [7, 8, 3], Absorbing at index 1
+        overwrite_sponge.absorb(&inputs[0..2]);
+        overwrite_sponge.absorb(&inputs[2..]);
+        assert_eq!(overwrite_sponge.state().state, vec![Fr::from(7u32), Fr::from(8u32), Fr::from(3u32)]);
+        assert_eq!(overwrite_sponge.state().mode, SpongeState::Absorbing{ next_absorb_index: 1});
+        // [7, 8, 3] -> permute -> [8, 10, 6]
+        assert_eq!(overwrite_sponge.squeeze(2), vec![Fr::from(8u32), Fr::from(10u32)]);
+        // The add mode sponge has [13, 8, 3] before the final permutation
+        add_sponge.absorb(&inputs);
+        assert_eq!(add_sponge.squeeze(2), vec![Fr::from(14u32), Fr::from(10u32)]);
+
+        let num_constraints = cs.num_constraints();
+        overwrite_gadget.absorb(&mut cs.ns(|| "absorb 1"), &input_gadgets[0..2])?;
+        overwrite_gadget.absorb(&mut cs.ns(|| "absorb 2"), &input_gadgets[2..])?;
+        assert_eq!(cs.num_constraints(), num_constraints);
+        let out = overwrite_gadget.squeeze(&mut cs.ns(|| "squeeze"), 2)?;
+        assert_eq!(values(&out), vec![Fr::from(8u32), Fr::from(10u32)]);
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+}
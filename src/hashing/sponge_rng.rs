@@ -0,0 +1,137 @@
+use algebra::prelude::*;
+use rand::{RngCore, SeedableRng};
+
+use crate::hashing::*;
+use crate::hashing::sponge::*;
+use crate::hashing::transcript::label_to_field_elements;
+
+/// The sponge `SpongeRng::from_seed` squeezes from, as `SeedableRng` is only given the seed.
+/// Implement this for a permutation to fix its parameters, and the rate and capacity it is used with.
+pub trait SpongeRngParams<F: PrimeField>: Permutation<F> + Clone
+{
+    fn rng_sponge() -> AlgebraicSponge<F, Self>;
+}
+
+/// A deterministic RNG, whose output is squeezed from an AlgebraicSponge that absorbed the seed.
+/// Its output is only as good as the permutation, so only use it with a cryptographic permutation
+/// such as Poseidon or Rescue when the randomness must be unpredictable.
+#[derive(Clone)]
+pub struct SpongeRng<F: PrimeField, P: Permutation<F> + Clone>
+{
+    sponge: AlgebraicSponge<F, P>,
+    // Squeezed bytes that have not been output yet, the next byte to output is last.
+    buffer: Vec<u8>,
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> SpongeRng<F, P>
+{
+    pub fn new(mut sponge: AlgebraicSponge<F, P>, seed: &[u8]) -> Self
+    {
+        sponge.reset();
+        sponge.absorb(&label_to_field_elements::<F>(b"SpongeRng"));
+        sponge.absorb(&label_to_field_elements::<F>(seed));
+        SpongeRng{sponge, buffer: Vec::new()}
+    }
+
+    // Refills the buffer with the whole bytes of a single squeezed field element
+    fn refill(&mut self)
+    {
+        let bytes_per_elem = bits_per_elem::<F>() / 8;
+        self.buffer = self.sponge.squeeze_bytes(bytes_per_elem);
+        self.buffer.reverse();
+    }
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> RngCore for SpongeRng<F, P>
+{
+    fn next_u32(&mut self) -> u32
+    {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64
+    {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8])
+    {
+        for byte in dest.iter_mut()
+        {
+            if self.buffer.is_empty()
+            {
+                self.refill();
+            }
+            *byte = self.buffer.pop().unwrap();
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error>
+    {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<F: PrimeField, P: SpongeRngParams<F>> SeedableRng for SpongeRng<F, P>
+{
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: Self::Seed) -> Self
+    {
+        SpongeRng::new(P::rng_sponge(), &seed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use algebra::{prelude::*, UniformRand};
+    use rand::{RngCore, SeedableRng};
+    use crate::alt_bn128::fr::Fr;
+    use crate::hashing::{dummy_permutation::*, sponge::*, sponge_rng::*};
+
+    impl SpongeRngParams<Fr> for MixingDummyPermutation<Fr> {
+        fn rng_sponge() -> AlgebraicSponge<Fr, Self> {
+            AlgebraicSponge::new(2, 1, MixingDummyPermutation{seed: Fr::from(3u32)})
+        }
+    }
+
+    #[test]
+    fn sponge_rng_determinism_test() {
+        let mut rng_1 = SpongeRng::<Fr, MixingDummyPermutation<Fr>>::from_seed([7u8; 32]);
+        let mut rng_2 = SpongeRng::<Fr, MixingDummyPermutation<Fr>>::from_seed([7u8; 32]);
+        let mut rng_3 = SpongeRng::<Fr, MixingDummyPermutation<Fr>>::from_seed([8u8; 32]);
+
+        let out_1: Vec<u64> = (0..10).map(|_| rng_1.next_u64()).collect();
+        let out_2: Vec<u64> = (0..10).map(|_| rng_2.next_u64()).collect();
+        let out_3: Vec<u64> = (0..10).map(|_| rng_3.next_u64()).collect();
+        assert_eq!(out_1, out_2);
+        assert_ne!(out_1, out_3);
+
+        assert_eq!(Fr::rand(&mut rng_1), Fr::rand(&mut rng_2));
+    }
+
+    #[test]
+    fn sponge_rng_matches_squeeze_bytes_test() {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P);
+        let mut rng = SpongeRng::new(sponge.clone(), b"seed");
+        let mut bytes = vec![0u8; 40];
+        rng.fill_bytes(&mut bytes);
+
+        // The output is the whole bytes of each squeezed element, in order
+        let mut expected_sponge = sponge;
+        expected_sponge.absorb(&label_to_field_elements::<Fr>(b"SpongeRng"));
+        expected_sponge.absorb(&label_to_field_elements::<Fr>(b"seed"));
+        // 15 whole bytes fit in the 125 usable bits of each element, so 40 bytes take 3 elements
+        let mut expected = Vec::new();
+        for _ in 0..3 {
+            expected.extend(expected_sponge.squeeze_bytes(15));
+        }
+        assert_eq!(bytes, expected[0..40].to_vec());
+    }
+}
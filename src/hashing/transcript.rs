@@ -0,0 +1,213 @@
+use algebra::prelude::*;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+
+use crate::hashing::*;
+use crate::hashing::sponge::*;
+use crate::hashing::hashchain::*;
+use crate::Error;
+
+/// Encodes a label as field elements, to be absorbed before the message it labels.
+/// The encoding is the label's length in bytes, followed by the label split into
+/// 8 byte chunks, each read as a little-endian u64. Prefixing the length keeps the encoding unambiguous.
+pub fn label_to_field_elements<F: PrimeField>(label: &[u8]) -> Vec<F>
+{
+    let mut elems = vec![F::from(label.len() as u64)];
+    for chunk in label.chunks(8)
+    {
+        let mut word = 0u64;
+        for (i, byte) in chunk.iter().enumerate()
+        {
+            word |= (*byte as u64) << (8 * i);
+        }
+        elems.push(F::from(word));
+    }
+    elems
+}
+
+/// A Fiat-Shamir transcript built on a HashChain.
+/// Every message and challenge is preceded by its label, so that messages cannot be confused
+/// with one another, and so that challenges are bound to where they are used in the protocol.
+pub struct Transcript<F: PrimeField, P: Permutation<F> + Clone>
+{
+    hashchain: HashChain<F, P>,
+}
+
+impl<F: PrimeField, P: Permutation<F> + Clone> Transcript<F, P>
+{
+    /// Creates a transcript for the protocol named by `protocol_label`.
+    pub fn new(sponge: AlgebraicSponge<F, P>, protocol_label: &[u8]) -> Self
+    {
+        let mut hashchain = HashChain::new(sponge);
+        hashchain.absorb(&label_to_field_elements::<F>(protocol_label));
+        Transcript{hashchain}
+    }
+
+    pub fn append_field_elements(&mut self, label: &[u8], elems: &[F])
+    {
+        let mut message = label_to_field_elements::<F>(label);
+        message.push(F::from(elems.len() as u64));
+        message.extend_from_slice(elems);
+        self.hashchain.absorb(&message);
+    }
+
+    pub fn append_commitment(&mut self, label: &[u8], root: F)
+    {
+        self.append_field_elements(label, &[root]);
+    }
+
+    pub fn challenge_scalar(&mut self, label: &[u8]) -> F
+    {
+        self.hashchain.absorb(&label_to_field_elements::<F>(label));
+        self.hashchain.squeeze(1)[0]
+    }
+
+    /// Squeezes `count` indices of `bits_each` bits each. See `AlgebraicSponge::squeeze_indices`.
+    pub fn challenge_indices(&mut self, label: &[u8], count: usize, bits_each: usize) -> Result<Vec<u64>, Error>
+    {
+        self.hashchain.absorb(&label_to_field_elements::<F>(label));
+        self.hashchain.squeeze_indices(count, bits_each)
+    }
+}
+
+/// The in-circuit counterpart of Transcript.
+/// Given the same messages, it derives exactly the same challenges as Transcript.
+pub struct TranscriptGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
+{
+    hashchain: HashChainGadget<F, FG, P>,
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> TranscriptGadget<F, FG, P>
+{
+    // Labels are public constants of the protocol, so they are absorbed as constants.
+    fn label_to_constants<CS: ConstraintSystem<F>>(mut cs: CS, label: &[u8]) -> Result<Vec<FG>, SynthesisError>
+    {
+        let zero = FG::zero(&mut cs.ns(|| "zero"))?;
+        let mut constants = Vec::new();
+        for (i, elem) in label_to_field_elements::<F>(label).iter().enumerate()
+        {
+            constants.push(zero.add_constant(&mut cs.ns(|| format!("label elem {:?}", i)), elem)?);
+        }
+        Ok(constants)
+    }
+
+    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, protocol_label: &[u8]) -> Result<Self, SynthesisError>
+    {
+        let mut hashchain = HashChainGadget::new(&mut cs.ns(|| "hashchain"), sponge)?;
+        let label = Self::label_to_constants(&mut cs.ns(|| "protocol label"), protocol_label)?;
+        hashchain.absorb(&mut cs.ns(|| "absorb protocol label"), &label)?;
+        Ok(TranscriptGadget{hashchain})
+    }
+
+    pub fn append_field_elements<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, label: &[u8], elems: &[FG]) -> Result<(), SynthesisError>
+    {
+        let mut message = Self::label_to_constants(&mut cs.ns(|| "label"), label)?;
+        let zero = FG::zero(&mut cs.ns(|| "zero"))?;
+        message.push(zero.add_constant(&mut cs.ns(|| "message length"), &F::from(elems.len() as u64))?);
+        message.extend_from_slice(elems);
+        self.hashchain.absorb(&mut cs.ns(|| "absorb message"), &message)
+    }
+
+    pub fn append_commitment<CS: ConstraintSystem<F>>(&mut self, cs: CS, label: &[u8], root: FG) -> Result<(), SynthesisError>
+    {
+        self.append_field_elements(cs, label, &[root])
+    }
+
+    pub fn challenge_scalar<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, label: &[u8]) -> Result<FG, SynthesisError>
+    {
+        let label = Self::label_to_constants(&mut cs.ns(|| "label"), label)?;
+        self.hashchain.absorb(&mut cs.ns(|| "absorb label"), &label)?;
+        Ok(self.hashchain.squeeze(&mut cs.ns(|| "squeeze"), 1)?[0].clone())
+    }
+
+    /// Returns each index as its bits in big-endian order. See `AlgebraicSpongeGadget::squeeze_indices`.
+    pub fn challenge_indices<CS: ConstraintSystem<F>>(&mut self, mut cs: CS, label: &[u8], count: usize, bits_each: usize) -> Result<Vec<Vec<Boolean>>, Error>
+    {
+        let label = Self::label_to_constants(&mut cs.ns(|| "label"), label)?;
+        self.hashchain.absorb(&mut cs.ns(|| "absorb label"), &label)?;
+        self.hashchain.squeeze_indices(&mut cs.ns(|| "squeeze"), count, bits_each)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::{ConstraintSystem, SynthesisError};
+    use algebra::prelude::*;
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::algebra::mux::int_to_constant_boolean_vec;
+    use crate::hashing::{dummy_permutation::*, sponge::*, transcript::*};
+    use crate::Error;
+
+    fn new_transcripts<CS: ConstraintSystem<Fr>>(mut cs: CS) -> Result<(
+        Transcript<Fr, MixingDummyPermutation<Fr>>,
+        TranscriptGadget<Fr, FrGadget, MixingDummyPermutation<Fr>>), SynthesisError>
+    {
+        let P = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, P.clone());
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, P)?;
+        let transcript = Transcript::new(sponge, b"test protocol");
+        let transcript_gadget = TranscriptGadget::new(&mut cs.ns(|| "transcript"), sponge_gadget, b"test protocol")?;
+        Ok((transcript, transcript_gadget))
+    }
+
+    #[test]
+    fn transcript_replay_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let (mut transcript, mut transcript_gadget) = new_transcripts(&mut cs.ns(|| "transcripts"))?;
+
+        let message = vec![Fr::from(10u32), Fr::from(11u32), Fr::from(12u32)];
+        let mut message_gadget = Vec::new();
+        for i in 0..message.len() {
+            message_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("message {:?}", i)), || Ok(message[i]))?);
+        }
+        let root = Fr::from(99u32);
+        let root_gadget = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(root))?;
+
+        transcript.append_commitment(b"oracle root", root);
+        transcript_gadget.append_commitment(&mut cs.ns(|| "append root"), b"oracle root", root_gadget)?;
+        let alpha = transcript.challenge_scalar(b"alpha");
+        let alpha_gadget = transcript_gadget.challenge_scalar(&mut cs.ns(|| "alpha"), b"alpha")?;
+        assert_eq!(alpha, alpha_gadget.get_value().unwrap());
+
+        transcript.append_field_elements(b"evaluations", &message);
+        transcript_gadget.append_field_elements(&mut cs.ns(|| "append evaluations"), b"evaluations", &message_gadget)?;
+        let queries = transcript.challenge_indices(b"queries", 5, 10)?;
+        let queries_gadget = transcript_gadget.challenge_indices(&mut cs.ns(|| "queries"), b"queries", 5, 10)?;
+        for i in 0..5 {
+            let expected = int_to_constant_boolean_vec(queries[i], 10);
+            assert_eq!(
+                expected.iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>(),
+                queries_gadget[i].iter().map(|b| b.get_value().unwrap()).collect::<Vec<_>>());
+        }
+
+        let beta = transcript.challenge_scalar(b"beta");
+        let beta_gadget = transcript_gadget.challenge_scalar(&mut cs.ns(|| "beta"), b"beta")?;
+        assert_eq!(beta, beta_gadget.get_value().unwrap());
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn transcript_label_separation_test() -> Result<(), SynthesisError> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let (mut transcript_1, mut transcript_gadget_1) = new_transcripts(&mut cs.ns(|| "transcripts 1"))?;
+        let (mut transcript_2, mut transcript_gadget_2) = new_transcripts(&mut cs.ns(|| "transcripts 2"))?;
+        let one = FrGadget::one(&mut cs.ns(|| "one"))?;
+
+        transcript_1.append_field_elements(b"a", &[Fr::one()]);
+        transcript_2.append_field_elements(b"b", &[Fr::one()]);
+        transcript_gadget_1.append_field_elements(&mut cs.ns(|| "append a"), b"a", &[one.clone()])?;
+        transcript_gadget_2.append_field_elements(&mut cs.ns(|| "append b"), b"b", &[one])?;
+        let challenge_1 = transcript_1.challenge_scalar(b"c");
+        let challenge_2 = transcript_2.challenge_scalar(b"c");
+        assert_ne!(challenge_1, challenge_2);
+        assert_eq!(transcript_gadget_1.challenge_scalar(&mut cs.ns(|| "challenge 1"), b"c")?.get_value().unwrap(), challenge_1);
+        assert_eq!(transcript_gadget_2.challenge_scalar(&mut cs.ns(|| "challenge 2"), b"c")?.get_value().unwrap(), challenge_2);
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use r1cs_std::{prelude::*, fields::fp::FpGadget};
 
 use crate::hashing::*;
 use crate::hashing::sponge::*;
+use crate::Error;
 use num_traits::*;
 
 pub struct TwoToOneHash<F: PrimeField, P: Permutation<F> + Clone>
@@ -13,11 +14,16 @@ pub struct TwoToOneHash<F: PrimeField, P: Permutation<F> + Clone>
 
 impl<F: PrimeField, P: Permutation<F> + Clone> TwoToOneHash<F, P>
 {
-    pub fn new(sponge: AlgebraicSponge<F, P>) -> Self
+    pub fn new(sponge: AlgebraicSponge<F, P>) -> Result<Self, Error>
     {
-        let mut sponge_c = sponge.clone();
-        sponge_c.reset();
-        TwoToOneHash{sponge: sponge_c}
+        Self::new_with_tree_depth(sponge, 0)
+    }
+
+    /// The sponge's capacity is initialized to the node hash IV, see `merkle_capacity_iv`.
+    pub fn new_with_tree_depth(sponge: AlgebraicSponge<F, P>, tree_depth: usize) -> Result<Self, Error>
+    {
+        let sponge_c = sponge.clone().with_capacity_iv(merkle_capacity_iv(MerkleHashDomain::Node, tree_depth))?;
+        Ok(TwoToOneHash{sponge: sponge_c})
     }
 
     pub fn hash(&self, left: F, right : F) -> F
@@ -29,6 +35,14 @@ impl<F: PrimeField, P: Permutation<F> + Clone> TwoToOneHash<F, P>
     }
 }
 
+impl<F: PrimeField, P: Permutation<F> + Clone> NodeHasher<F> for TwoToOneHash<F, P>
+{
+    fn hash_node(&self, left: F, right: F) -> F
+    {
+        self.hash(left, right)
+    }
+}
+
 pub struct TwoToOneHashGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
 {
     sponge: AlgebraicSpongeGadget<F, FG, P>,
@@ -36,18 +50,31 @@ pub struct TwoToOneHashGadget<F: PrimeField, FG: FieldGadget<F, F>, P: Permutati
 
 impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> TwoToOneHashGadget<F, FG, P>
 {
-    pub fn new<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Self
+    pub fn new<CS: ConstraintSystem<F>>(cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>) -> Result<Self, Error>
     {
-        let mut sponge_c = sponge.clone();
-        sponge_c.reset(&mut cs);
-        TwoToOneHashGadget{sponge: sponge_c}
+        Self::new_with_tree_depth(cs, sponge, 0)
+    }
+
+    /// See `TwoToOneHash::new_with_tree_depth`
+    pub fn new_with_tree_depth<CS: ConstraintSystem<F>>(mut cs: CS, sponge: AlgebraicSpongeGadget<F, FG, P>, tree_depth: usize) -> Result<Self, Error>
+    {
+        let sponge_c = sponge.clone().with_capacity_iv(&mut cs, merkle_capacity_iv(MerkleHashDomain::Node, tree_depth))?;
+        Ok(TwoToOneHashGadget{sponge: sponge_c})
     }
 
     pub fn hash<CS: ConstraintSystem<F>>(&self, mut cs: CS, left: FG, right : FG) -> Result<FG, SynthesisError>
     {
         let mut sponge_c = self.sponge.clone();
-        sponge_c.absorb(&mut cs, &[left, right]);
+        sponge_c.absorb(&mut cs, &[left, right])?;
         // TODO: Make generic for smaller fields
         Ok(sponge_c.squeeze(&mut cs, 1)?[0].clone())
     }
-}
\ No newline at end of file
+}
+
+impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> NodeHasherGadget<F, FG> for TwoToOneHashGadget<F, FG, P>
+{
+    fn hash_node<CS: ConstraintSystem<F>>(&self, cs: CS, left: FG, right: FG) -> Result<FG, SynthesisError>
+    {
+        self.hash(cs, left, right)
+    }
+}
@@ -6,7 +6,8 @@ extern crate derivative;
 
 pub mod algebra;
 pub mod alt_bn128;
+pub mod error;
 pub mod hashing;
 pub mod merkle_tree;
 
-pub type Error = Box<dyn std::error::Error>;
\ No newline at end of file
+pub use error::Error;
@@ -0,0 +1,202 @@
+use algebra::fields::PrimeField;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{prelude::*};
+use crate::hashing::*;
+use crate::algebra::mux::*;
+use crate::merkle_tree::*;
+use crate::Error;
+
+fn cap_height(cap_len: usize) -> Result<usize, Error>
+{
+    if cap_len == 0 || cap_len & (cap_len - 1) != 0
+    {
+        return Err(Error::NotPowerOfTwo(cap_len));
+    }
+    Ok(cap_len.trailing_zeros() as usize)
+}
+
+/// Hashes a cap of 2^c nodes up to the root of the tree.
+pub fn root_from_cap<F: PrimeField, NH: NodeHasher<F>>(node_hasher: &NH, cap: &[F]) -> Result<F, Error>
+{
+    cap_height(cap.len())?;
+    let mut layer = cap.to_vec();
+    while layer.len() > 1
+    {
+        layer = layer.chunks(2).map(|pair| node_hasher.hash_node(pair[0], pair[1])).collect();
+    }
+    Ok(layer[0])
+}
+
+/// An authentication path against a Merkle cap, the 2^c nodes at distance c from the root.
+/// path is the full leaf index in big-endian bits, whose first c bits select the cap entry.
+/// auth_path is the leaf's MerklePath auth_path without its first c entries, so it ends at the cap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleCapPath<F: PrimeField>
+{
+    pub path: Vec<bool>,
+    pub auth_path: Vec<F>,
+}
+
+impl<F: PrimeField> MerkleCapPath<F>
+{
+    pub fn verify<LH: LeafHasher<F>, NH: NodeHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        cap: &[F],
+        leaf: &[F]) -> bool
+    {
+        let height = match cap_height(cap.len()) {
+            Ok(height) => height,
+            Err(_) => return false,
+        };
+        if self.path.len() != height + self.auth_path.len()
+        {
+            return false;
+        }
+        let cap_index = self.path[0..height].iter().fold(0, |acc, bit| (acc << 1) | (*bit as usize));
+        let subpath = MerklePath{path: self.path[height..].to_vec(), auth_path: self.auth_path.clone()};
+        match subpath.compute_root(leaf_hasher, node_hasher, leaf) {
+            Ok(cap_entry) => cap_entry == cap[cap_index],
+            Err(_) => false,
+        }
+    }
+}
+
+impl<F: PrimeField, LH: LeafHasher<F> + Sync, NH: NodeHasher<F> + Sync> MerkleTree<F, LH, NH>
+{
+    /// The 2^cap_height nodes at distance cap_height from the root, left to right.
+    pub fn cap(&self, cap_height: usize) -> Result<Vec<F>, Error>
+    {
+        if cap_height > self.depth()
+        {
+            return Err(Error::IndexOutOfBounds{index: cap_height, len: self.depth() + 1});
+        }
+        Ok(self.layers[self.depth() - cap_height].clone())
+    }
+
+    pub fn generate_cap_proof(&self, index: usize, cap_height: usize) -> Result<MerkleCapPath<F>, Error>
+    {
+        if cap_height > self.depth()
+        {
+            return Err(Error::IndexOutOfBounds{index: cap_height, len: self.depth() + 1});
+        }
+        let proof = self.generate_proof(index)?;
+        Ok(MerkleCapPath{path: proof.path, auth_path: proof.auth_path[cap_height..].to_vec()})
+    }
+}
+
+impl<LH, NH> MerklePathGadget<LH, NH>
+{
+    /// Verifies a path against a Merkle cap instead of a single root, in the layout of MerkleCapPath.
+    /// The cap entry is selected by the first log2(cap.len()) path bits.
+    pub fn verify_with_cap<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        path: &[Boolean],
+        cap: &[FG],
+        auth_path: &[FG],
+        leaf: &[FG]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let height = cap_height(cap.len())?;
+        if path.len() != height + auth_path.len()
+        {
+            return Err(Error::InvalidLength{expected: height + auth_path.len(), found: path.len()});
+        }
+        let curHash = self.compute_root(&mut cs.ns(|| "compute cap entry"), &path[height..], auth_path, leaf)?;
+        let cap_entry = mux(&mut cs.ns(|| "cap mux"), cap, &path[0..height])?;
+        curHash.enforce_equal(&mut cs.ns(|| "cap check"), &cap_entry)?;
+        Ok(())
+    }
+
+    /// See `root_from_cap`.
+    pub fn compute_root_from_cap<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        cap: &[FG]) -> Result<FG, Error>
+    where
+        NH: NodeHasherGadget<F, FG>
+    {
+        cap_height(cap.len())?;
+        let mut layer = cap.to_vec();
+        let mut level = 0;
+        while layer.len() > 1
+        {
+            let mut next = Vec::with_capacity(layer.len() / 2);
+            for j in 0..(layer.len() / 2)
+            {
+                next.push(self.nodeHasher.hash_node(&mut cs.ns(|| format!("cap layer {:?} node {:?}", level, j)),
+                    layer[2 * j].clone(), layer[2 * j + 1].clone())?);
+            }
+            layer = next;
+            level += 1;
+        }
+        Ok(layer[0].clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::merkle_tree::{*, cap::*};
+    use crate::hashing::{sponge::*, leafhash::*, two_to_one_hash::*, dummy_permutation::*};
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    #[test]
+    fn cap_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let leaves: Vec<Vec<Fr>> = (0..16u32).map(|i| vec![Fr::from(i), Fr::from(i + 7)]).collect();
+        let tree = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?, &leaves)?;
+
+        let cap_height = 2;
+        let cap = tree.cap(cap_height)?;
+        assert_eq!(cap.len(), 4);
+        assert_eq!(root_from_cap(&tree.node_hasher, &cap)?, tree.root());
+        assert_eq!(tree.cap(0)?, vec![tree.root()]);
+        for i in 0..leaves.len() {
+            let proof = tree.generate_cap_proof(i, cap_height)?;
+            assert_eq!(proof.auth_path.len(), 2);
+            assert!(proof.verify(&tree.leaf_hasher, &tree.node_hasher, &cap, &leaves[i]));
+            assert!(!proof.verify(&tree.leaf_hasher, &tree.node_hasher, &cap, &leaves[(i + 1) % 16]));
+        }
+
+        let index = 11;
+        let proof = tree.generate_cap_proof(index, cap_height)?;
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let mut path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+        }
+        let mut auth_path = Vec::new();
+        for i in 0..proof.auth_path.len() {
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let mut cap_gadget = Vec::new();
+        for i in 0..cap.len() {
+            cap_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("cap {:?}", i)), || Ok(cap[i]))?);
+        }
+        let leaf = vec![
+            FrGadget::alloc(&mut cs.ns(|| "leaf 0"), || Ok(leaves[index][0]))?,
+            FrGadget::alloc(&mut cs.ns(|| "leaf 1"), || Ok(leaves[index][1]))?];
+        MT_path_gadget.verify_with_cap(&mut cs.ns(|| "MT"), &path, &cap_gadget, &auth_path, &leaf)?;
+        let root = MT_path_gadget.compute_root_from_cap(&mut cs.ns(|| "cap root"), &cap_gadget)?;
+        assert_eq!(root.get_value().unwrap(), tree.root());
+        assert!(cs.is_satisfied());
+
+        // The path must match the cap entry its top bits select
+        cap_gadget.swap(1, 2);
+        MT_path_gadget.verify_with_cap(&mut cs.ns(|| "swapped cap"), &path, &cap_gadget, &auth_path, &leaf)?;
+        assert!(!cs.is_satisfied());
+        Ok(())
+    }
+}
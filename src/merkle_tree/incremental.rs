@@ -0,0 +1,328 @@
+use algebra::fields::PrimeField;
+use crate::hashing::*;
+use crate::merkle_tree::*;
+use crate::Error;
+use std::collections::BTreeMap;
+
+// Everything checkpoint saves and rewind restores.
+#[derive(Clone)]
+struct IncrementalState<F: PrimeField>
+{
+    num_leaves: usize,
+    // frontier[h] is the last completed left child at height h, which is only current while bit h of num_leaves is set.
+    // frontier[depth] is the root once the tree is full.
+    frontier: Vec<F>,
+    // For each marked leaf, its siblings by height, as far as they are complete.
+    witnesses: BTreeMap<usize, Vec<Option<F>>>,
+}
+
+/// An append-only Merkle tree of a fixed depth, which only stores its frontier,
+/// along with the authentication paths of the leaves it is asked to keep track of.
+/// Positions which haven't been appended yet hold an empty leaf, whose hash is zero,
+/// and the roots of empty subtrees are precomputed per height.
+/// The paths it returns are in the layout MerklePath and MerklePathGadget::verify use.
+pub struct IncrementalMerkleTree<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>>
+{
+    leaf_hasher: LH,
+    node_hasher: NH,
+    // empty_roots[h] is the root of an empty subtree of height h, so empty_roots[0] is the empty leaf.
+    empty_roots: Vec<F>,
+    state: IncrementalState<F>,
+    checkpoints: Vec<IncrementalState<F>>,
+}
+
+impl<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>> IncrementalMerkleTree<F, LH, NH>
+{
+    pub fn new(leaf_hasher: LH, node_hasher: NH, depth: usize) -> Result<Self, Error>
+    {
+        // Leaf indices are counted in a usize
+        let max_depth = 8 * std::mem::size_of::<usize>() - 1;
+        if depth > max_depth
+        {
+            return Err(Error::TreeTooDeep{depth, max_depth});
+        }
+        let mut empty_roots = vec![F::zero()];
+        for h in 0..depth
+        {
+            empty_roots.push(node_hasher.hash_node(empty_roots[h], empty_roots[h]));
+        }
+        let state = IncrementalState{num_leaves: 0, frontier: vec![F::zero(); depth + 1], witnesses: BTreeMap::new()};
+        Ok(IncrementalMerkleTree{leaf_hasher, node_hasher, empty_roots, state, checkpoints: Vec::new()})
+    }
+
+    pub fn depth(&self) -> usize
+    {
+        self.empty_roots.len() - 1
+    }
+
+    pub fn num_leaves(&self) -> usize
+    {
+        self.state.num_leaves
+    }
+
+    /// The root of an empty subtree of the given height.
+    pub fn empty_root(&self, height: usize) -> F
+    {
+        self.empty_roots[height]
+    }
+
+    /// Appends a leaf, returning its index.
+    pub fn append(&mut self, leaf: &[F]) -> Result<usize, Error>
+    {
+        let depth = self.depth();
+        let index = self.state.num_leaves;
+        if index == 1 << depth
+        {
+            return Err(Error::IndexOutOfBounds{index, len: 1 << depth});
+        }
+
+        // Walk up while the current node is a right child, recording every node this append completes.
+        let mut cur = self.leaf_hasher.hash_leaf(leaf);
+        let mut completed = vec![cur];
+        let mut height = 0;
+        while height < depth && (index >> height) & 1 == 1
+        {
+            cur = self.node_hasher.hash_node(self.state.frontier[height], cur);
+            completed.push(cur);
+            height += 1;
+        }
+        self.state.frontier[height] = cur;
+        self.state.num_leaves += 1;
+
+        // Marked leaves whose right sibling was just completed can now fill it in.
+        for (marked, siblings) in self.state.witnesses.iter_mut()
+        {
+            for h in 0..siblings.len().min(completed.len())
+            {
+                if (marked >> h) & 1 == 0 && (marked >> h) + 1 == index >> h
+                {
+                    siblings[h] = Some(completed[h]);
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Keeps track of the authentication path of the last appended leaf, returning its index.
+    pub fn mark(&mut self) -> Option<usize>
+    {
+        if self.state.num_leaves == 0
+        {
+            return None;
+        }
+        let index = self.state.num_leaves - 1;
+        // The left siblings of the leaf are on the frontier, as the leaf was the last one appended.
+        let siblings = (0..self.depth()).map(|h|
+            if (index >> h) & 1 == 1 {
+                Some(self.state.frontier[h])
+            } else {
+                None
+            }).collect();
+        self.state.witnesses.insert(index, siblings);
+        Some(index)
+    }
+
+    /// Stops keeping track of the leaf at index, returning whether it was marked.
+    pub fn unmark(&mut self, index: usize) -> bool
+    {
+        self.state.witnesses.remove(&index).is_some()
+    }
+
+    // partial[h] is the node at height h containing the first empty position, which is the root if the tree is full.
+    fn partial_nodes(&self) -> Vec<F>
+    {
+        let depth = self.depth();
+        let num_leaves = self.state.num_leaves;
+        if num_leaves == 1 << depth
+        {
+            return vec![self.state.frontier[depth]; depth + 1];
+        }
+        let mut partial = vec![self.empty_roots[0]];
+        for h in 0..depth
+        {
+            let cur = partial[h];
+            partial.push(if (num_leaves >> h) & 1 == 1 {
+                self.node_hasher.hash_node(self.state.frontier[h], cur)
+            } else {
+                self.node_hasher.hash_node(cur, self.empty_roots[h])
+            });
+        }
+        partial
+    }
+
+    pub fn root(&self) -> F
+    {
+        self.partial_nodes()[self.depth()]
+    }
+
+    /// Returns the current authentication path of a marked leaf, or None if the leaf isn't marked.
+    pub fn witness(&self, index: usize) -> Option<MerklePath<F>>
+    {
+        let siblings = self.state.witnesses.get(&index)?;
+        let depth = self.depth();
+        let partial = self.partial_nodes();
+        let mut path = vec![false; depth];
+        let mut auth_path = vec![F::zero(); depth];
+        for h in 0..depth
+        {
+            path[depth - 1 - h] = (index >> h) & 1 == 1;
+            auth_path[depth - 1 - h] = match siblings[h] {
+                Some(sibling) => sibling,
+                // An incomplete right sibling is either still empty, or contains the first empty position
+                None => if self.state.num_leaves <= ((index >> h) + 1) << h {
+                    self.empty_roots[h]
+                } else {
+                    partial[h]
+                },
+            };
+        }
+        Some(MerklePath{path, auth_path})
+    }
+
+    /// Saves the current state, to be restored by rewind.
+    pub fn checkpoint(&mut self)
+    {
+        self.checkpoints.push(self.state.clone());
+    }
+
+    /// Restores the state at the last checkpoint, undoing the appends and marks since.
+    /// Returns false, and does nothing, if there is no checkpoint.
+    pub fn rewind(&mut self) -> bool
+    {
+        match self.checkpoints.pop() {
+            Some(state) => {
+                self.state = state;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::merkle_tree::{*, incremental::*};
+    use crate::hashing::{sponge::*, leafhash::*, two_to_one_hash::*, dummy_permutation::*};
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    type TestTree = IncrementalMerkleTree<Fr, LeafHash<Fr, MixingDummyPermutation<Fr>>, TwoToOneHash<Fr, MixingDummyPermutation<Fr>>>;
+
+    fn new_tree(depth: usize) -> Result<TestTree, Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation);
+        IncrementalMerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?, depth)
+    }
+
+    // Recomputes the root from all the leaves, padding with empty leaves.
+    fn naive_root(depth: usize, leaves: &[Vec<Fr>]) -> Result<Fr, Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation);
+        let leaf_hash = LeafHash::new(sponge.clone())?;
+        let node_hash = TwoToOneHash::new(sponge)?;
+        let mut layer: Vec<Fr> = (0..(1 << depth)).map(|i|
+            if i < leaves.len() { leaf_hash.hash(&leaves[i]) } else { Fr::zero() }).collect();
+        while layer.len() > 1 {
+            layer = layer.chunks(2).map(|pair| node_hash.hash(pair[0], pair[1])).collect();
+        }
+        Ok(layer[0])
+    }
+
+    #[test]
+    fn incremental_mt_test() -> Result<(), Error> {
+        let depth = 4;
+        let mut tree = new_tree(depth)?;
+        assert_eq!(tree.root(), tree.empty_root(depth));
+        assert_eq!(tree.root(), naive_root(depth, &[])?);
+
+        let leaves: Vec<Vec<Fr>> = (0..16u32).map(|i| vec![Fr::from(i), Fr::from(i + 100)]).collect();
+        let marked = vec![0, 2, 7, 8, 13];
+        for i in 0..leaves.len() {
+            assert_eq!(tree.append(&leaves[i])?, i);
+            if marked.contains(&i) {
+                assert_eq!(tree.mark(), Some(i));
+            }
+            let root = tree.root();
+            assert_eq!(root, naive_root(depth, &leaves[0..(i + 1)])?);
+            for m in marked.iter().filter(|m| **m <= i) {
+                let proof = tree.witness(*m).unwrap();
+                assert!(proof.verify(&tree.leaf_hasher, &tree.node_hasher, root, &leaves[*m]));
+            }
+        }
+        match tree.append(&leaves[0]) {
+            Err(Error::IndexOutOfBounds{index: 16, len: 16}) => (),
+            _ => panic!("appending to a full tree should fail"),
+        };
+        assert!(tree.unmark(2));
+        assert!(tree.witness(2).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_mt_rewind_test() -> Result<(), Error> {
+        let depth = 3;
+        let mut tree = new_tree(depth)?;
+        let leaves: Vec<Vec<Fr>> = (0..6u32).map(|i| vec![Fr::from(i)]).collect();
+        assert!(!tree.rewind());
+        for i in 0..3 {
+            tree.append(&leaves[i])?;
+        }
+        tree.mark();
+        tree.checkpoint();
+        let root = tree.root();
+        let proof = tree.witness(2).unwrap();
+
+        for i in 3..6 {
+            tree.append(&leaves[i])?;
+        }
+        tree.mark();
+        assert_ne!(tree.root(), root);
+        assert_ne!(tree.witness(2).unwrap(), proof);
+
+        assert!(tree.rewind());
+        assert_eq!(tree.num_leaves(), 3);
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.witness(2).unwrap(), proof);
+        assert!(tree.witness(5).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_mt_gadget_test() -> Result<(), Error> {
+        let depth = 5;
+        let mut tree = new_tree(depth)?;
+        let leaves: Vec<Vec<Fr>> = (0..11u32).map(|i| vec![Fr::from(i)]).collect();
+        for i in 0..leaves.len() {
+            tree.append(&leaves[i])?;
+            if i == 4 {
+                tree.mark();
+            }
+        }
+        let index = 4;
+        let proof = tree.witness(index).unwrap();
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let mut path = Vec::new();
+        let mut auth_path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let leaf = vec![FrGadget::alloc(&mut cs.ns(|| "leaf"), || Ok(leaves[index][0]))?];
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        MT_path_gadget.verify(&mut cs.ns(|| "MT"), &path, root, &auth_path, &leaf)?;
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+}
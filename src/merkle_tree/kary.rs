@@ -0,0 +1,319 @@
+use algebra::fields::PrimeField;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{prelude::*};
+use crate::hashing::*;
+use crate::algebra::mux::*;
+use crate::Error;
+use rayon::prelude::*;
+
+fn log_arity(arity: usize) -> Result<usize, Error>
+{
+    if arity < 2 || arity & (arity - 1) != 0
+    {
+        return Err(Error::NotPowerOfTwo(arity));
+    }
+    Ok(arity.trailing_zeros() as usize)
+}
+
+/// An authentication path in a k-ary Merkle tree.
+/// path is the leaf index in big-endian bits, log2(k) bits per level, so its last log2(k) bits are index % k.
+/// auth_path[i] holds the k - 1 siblings of the node on the path at level i, in order, with that node left out.
+/// As in MerklePath, auth_path[0] holds children of the root, and the last entry holds the leaf's siblings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KaryMerklePath<F: PrimeField>
+{
+    pub path: Vec<bool>,
+    pub auth_path: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField> KaryMerklePath<F>
+{
+    /// Computes the root implied by this path and leaf.
+    pub fn compute_root<LH: LeafHasher<F>, NH: KToOneHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        leaf: &[F]) -> Result<F, Error>
+    {
+        let arity = node_hasher.arity();
+        let bits_per_level = log_arity(arity)?;
+        if self.path.len() != self.auth_path.len() * bits_per_level
+        {
+            return Err(Error::InvalidLength{expected: self.auth_path.len() * bits_per_level, found: self.path.len()});
+        }
+        let mut cur_hash = leaf_hasher.hash_leaf(leaf);
+        // Traverse up the tree, so iterate over the levels in reverse.
+        for i in (0..self.auth_path.len()).rev()
+        {
+            if self.auth_path[i].len() != arity - 1
+            {
+                return Err(Error::InvalidLength{expected: arity - 1, found: self.auth_path[i].len()});
+            }
+            let position = self.path[(i * bits_per_level)..((i + 1) * bits_per_level)].iter()
+                .fold(0, |acc, bit| (acc << 1) | (*bit as usize));
+            let mut children = self.auth_path[i].clone();
+            children.insert(position, cur_hash);
+            cur_hash = node_hasher.hash_children(&children)?;
+        }
+        Ok(cur_hash)
+    }
+
+    pub fn verify<LH: LeafHasher<F>, NH: KToOneHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        root: F,
+        leaf: &[F]) -> bool
+    {
+        match self.compute_root(leaf_hasher, node_hasher, leaf) {
+            Ok(computed_root) => computed_root == root,
+            Err(_) => false,
+        }
+    }
+}
+
+/// A k-ary Merkle tree over a power of k number of leaves, where k is the arity of the node hasher.
+pub struct KaryMerkleTree<F: PrimeField, LH: LeafHasher<F>, NH: KToOneHasher<F>>
+{
+    leaf_hasher: LH,
+    node_hasher: NH,
+    // layers[0] holds the leaf hashes, and layers[depth] holds just the root.
+    layers: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField, LH: LeafHasher<F> + Sync, NH: KToOneHasher<F> + Sync> KaryMerkleTree<F, LH, NH>
+{
+    /// Builds the tree, hashing each layer in parallel.
+    pub fn new(leaf_hasher: LH, node_hasher: NH, leaves: &[Vec<F>]) -> Result<Self, Error>
+    {
+        let arity = node_hasher.arity();
+        let bits_per_level = log_arity(arity)?;
+        let num_leaves = leaves.len();
+        if num_leaves == 0 || num_leaves & (num_leaves - 1) != 0 || num_leaves.trailing_zeros() as usize % bits_per_level != 0
+        {
+            return Err(Error::NotPowerOfArity{len: num_leaves, arity});
+        }
+
+        let mut layers = vec![leaves.par_iter().map(|leaf| leaf_hasher.hash_leaf(leaf)).collect::<Vec<F>>()];
+        while layers[layers.len() - 1].len() > 1
+        {
+            let next_layer = layers[layers.len() - 1].par_chunks(arity)
+                .map(|children| node_hasher.hash_children(children))
+                .collect::<Result<Vec<F>, Error>>()?;
+            layers.push(next_layer);
+        }
+
+        Ok(KaryMerkleTree{leaf_hasher, node_hasher, layers})
+    }
+
+    pub fn root(&self) -> F
+    {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    pub fn depth(&self) -> usize
+    {
+        self.layers.len() - 1
+    }
+
+    pub fn arity(&self) -> usize
+    {
+        self.node_hasher.arity()
+    }
+
+    pub fn num_leaves(&self) -> usize
+    {
+        self.layers[0].len()
+    }
+
+    pub fn generate_proof(&self, index: usize) -> Result<KaryMerklePath<F>, Error>
+    {
+        if index >= self.num_leaves()
+        {
+            return Err(Error::IndexOutOfBounds{index, len: self.num_leaves()});
+        }
+        let arity = self.arity();
+        let bits_per_level = arity.trailing_zeros() as usize;
+        let depth = self.depth();
+        let mut path = vec![false; depth * bits_per_level];
+        let mut auth_path = vec![Vec::new(); depth];
+        // Walk up from the leaves, filling in the path from its end.
+        let mut cur_index = index;
+        for level in 0..depth
+        {
+            let position = cur_index % arity;
+            let first_sibling = cur_index - position;
+            let mut siblings = self.layers[level][first_sibling..(first_sibling + arity)].to_vec();
+            siblings.remove(position);
+            auth_path[depth - 1 - level] = siblings;
+            for j in 0..bits_per_level
+            {
+                path[(depth - level) * bits_per_level - 1 - j] = (position >> j) & 1 == 1;
+            }
+            cur_index /= arity;
+        }
+        Ok(KaryMerklePath{path, auth_path})
+    }
+
+    pub fn verify(&self, root: F, proof: &KaryMerklePath<F>, leaf: &[F]) -> bool
+    {
+        proof.verify(&self.leaf_hasher, &self.node_hasher, root, leaf)
+    }
+}
+
+/// Verifies k-ary Merkle paths in-circuit. Each level consumes log2(k) path bits and k - 1 siblings.
+pub struct KaryMerklePathGadget<LH, NH>
+{
+    pub leafHasher: LH,
+    pub nodeHasher: NH,
+}
+
+impl<LH, NH> KaryMerklePathGadget<LH, NH>
+{
+    // The path and authentication path are laid out as in KaryMerklePath.
+    pub fn verify<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        path: &[Boolean],
+        root: FG,
+        auth_path: &[Vec<FG>],
+        leaf: &[FG]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: KToOneHasherGadget<F, FG>
+    {
+        let arity = self.nodeHasher.arity();
+        let bits_per_level = log_arity(arity)?;
+        if path.len() != auth_path.len() * bits_per_level
+        {
+            return Err(Error::InvalidLength{expected: auth_path.len() * bits_per_level, found: path.len()});
+        }
+
+        let mut curHash = self.leafHasher.hash_leaf(&mut cs.ns(|| "leafhash"), leaf)?;
+        // To traverse up a MT, we iterate over the levels in reverse.
+        for i in (0..auth_path.len()).rev()
+        {
+            let mut cs_i = cs.ns(|| format!("MT layer {:?}", i));
+            let siblings = &auth_path[i];
+            if siblings.len() != arity - 1
+            {
+                return Err(Error::InvalidLength{expected: arity - 1, found: siblings.len()});
+            }
+            let position = &path[(i * bits_per_level)..((i + 1) * bits_per_level)];
+            let selectors = one_hot(&mut cs_i.ns(|| "position selectors"), position)?;
+
+            // Child j is curHash if position = j, siblings[j] if position > j, and siblings[j - 1] if position < j.
+            // That is siblings[j] + [position = j] * (curHash - siblings[j]) + [position < j] * (siblings[j - 1] - siblings[j]),
+            // which costs at most two constraints per child, as [position < j] is a sum of selectors.
+            // The first and last child only ever take one of their two sibling candidates.
+            let mut children = Vec::with_capacity(arity);
+            let mut below = FG::zero(&mut cs_i.ns(|| "zero"))?;
+            for j in 0..arity
+            {
+                let mut cs_j = cs_i.ns(|| format!("child {:?}", j));
+                let sibling = if j == arity - 1 { &siblings[j - 1] } else { &siblings[j] };
+                let current_diff = curHash.sub(&mut cs_j.ns(|| "current minus sibling"), sibling)?;
+                let current_term = selectors[j].mul(&mut cs_j.ns(|| "current term"), &current_diff)?;
+                let mut child = sibling.add(&mut cs_j.ns(|| "select current"), &current_term)?;
+                if j > 0 && j < arity - 1
+                {
+                    let left_diff = siblings[j - 1].sub(&mut cs_j.ns(|| "left sibling minus sibling"), &siblings[j])?;
+                    let left_term = below.mul(&mut cs_j.ns(|| "left sibling term"), &left_diff)?;
+                    child.add_in_place(&mut cs_j.ns(|| "select left sibling"), &left_term)?;
+                }
+                below.add_in_place(&mut cs_j.ns(|| "position below next child"), &selectors[j])?;
+                children.push(child);
+            }
+
+            curHash = self.nodeHasher.hash_children(&mut cs_i.ns(|| "node hash"), &children)?;
+        }
+
+        curHash.enforce_equal(&mut cs.ns(|| "root check"), &root)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::merkle_tree::kary::*;
+    use crate::hashing::{sponge::*, leafhash::*, k_to_one_hash::*, dummy_permutation::*};
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    fn kary_mt_test_helper(arity: usize, depth: u32) -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(arity, 1, permutation.clone());
+        let num_leaves = arity.pow(depth);
+        let leaves: Vec<Vec<Fr>> = (0..num_leaves as u32).map(|i| vec![Fr::from(i), Fr::from(i + 1)]).collect();
+        let tree = KaryMerkleTree::new(LeafHash::new(sponge.clone())?, KToOneHash::new(sponge.clone(), arity)?, &leaves)?;
+        assert_eq!(tree.depth(), depth as usize);
+
+        for i in 0..num_leaves {
+            let proof = tree.generate_proof(i)?;
+            assert!(tree.verify(tree.root(), &proof, &leaves[i]));
+            assert!(!tree.verify(tree.root(), &proof, &leaves[(i + 1) % num_leaves]));
+        }
+
+        // The native paths are in the layout the gadget consumes
+        let index = num_leaves - 2;
+        let proof = tree.generate_proof(index)?;
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), arity, 1, permutation)?;
+        let MT_path_gadget = KaryMerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: KToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget, arity)?,
+        };
+        let mut path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+        }
+        let mut auth_path = Vec::new();
+        for i in 0..proof.auth_path.len() {
+            let mut siblings = Vec::new();
+            for j in 0..proof.auth_path[i].len() {
+                siblings.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?} {:?}", i, j)), || Ok(proof.auth_path[i][j]))?);
+            }
+            auth_path.push(siblings);
+        }
+        let mut leaf = Vec::new();
+        for i in 0..leaves[index].len() {
+            leaf.push(FrGadget::alloc(&mut cs.ns(|| format!("leaf {:?}", i)), || Ok(leaves[index][i]))?);
+        }
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        let num_constraints = cs.num_constraints();
+        MT_path_gadget.verify(&mut cs.ns(|| "MT"), &path, root, &auth_path, &leaf)?;
+        assert!(cs.is_satisfied());
+        // The dummy permutation is linear, so this is the cost of selecting the children, which is linear in the arity
+        assert!(cs.num_constraints() - num_constraints <= depth as usize * 3 * arity + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn kary_mt_test() -> Result<(), Error> {
+        kary_mt_test_helper(2, 3)?;
+        kary_mt_test_helper(4, 2)?;
+        kary_mt_test_helper(8, 2)?;
+        kary_mt_test_helper(16, 1)
+    }
+
+    #[test]
+    fn kary_mt_root_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(4, 1, permutation);
+        let leaf_hash = LeafHash::new(sponge.clone())?;
+        let node_hash = KToOneHash::new(sponge.clone(), 4)?;
+        let leaves: Vec<Vec<Fr>> = (0..4u32).map(|i| vec![Fr::from(i)]).collect();
+        let tree = KaryMerkleTree::new(LeafHash::new(sponge.clone())?, KToOneHash::new(sponge.clone(), 4)?, &leaves)?;
+        let leaf_hashes: Vec<Fr> = leaves.iter().map(|leaf| leaf_hash.hash(leaf)).collect();
+        assert_eq!(tree.root(), node_hash.hash(&leaf_hashes)?);
+
+        // 8 leaves is a power of two, but not of four
+        let leaves: Vec<Vec<Fr>> = (0..8u32).map(|i| vec![Fr::from(i)]).collect();
+        match KaryMerkleTree::new(leaf_hash, node_hash, &leaves) {
+            Err(Error::NotPowerOfArity{len: 8, arity: 4}) => (),
+            _ => panic!("8 leaves should fail for a 4-ary tree"),
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use algebra::fields::PrimeField;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{prelude::*};
+use crate::hashing::*;
+use crate::algebra::{mux::*, bits::to_bits_fixed};
+use crate::merkle_tree::*;
+use crate::Error;
+
+/// Bags the peaks of a Merkle Mountain Range into its root.
+/// The peaks are given left to right, i.e. by decreasing height, and are folded in from the right,
+/// so the root is hash(num_leaves, hash(peaks[0], hash(peaks[1], ... peaks[k - 1]))).
+/// Hashing in the number of leaves commits to the heights of the peaks. Without any peaks, the bag is zero.
+pub fn bag_peaks<F: PrimeField, NH: NodeHasher<F>>(node_hasher: &NH, num_leaves: usize, peaks: &[F]) -> F
+{
+    let mut bag = match peaks.last() {
+        Some(peak) => *peak,
+        None => F::zero(),
+    };
+    for peak in peaks.iter().rev().skip(1)
+    {
+        bag = node_hasher.hash_node(*peak, bag);
+    }
+    node_hasher.hash_node(F::from(num_leaves as u64), bag)
+}
+
+// The height of the mountain holding the leaf at index, in a range of num_leaves leaves.
+// This is the highest bit in which index and num_leaves differ, which is set in num_leaves as index < num_leaves.
+fn mountain_height(index: usize, num_leaves: usize) -> usize
+{
+    (0usize.leading_zeros() - (index ^ num_leaves).leading_zeros() - 1) as usize
+}
+
+/// An inclusion proof for a leaf of a MerkleMountainRange.
+/// path authenticates the leaf against the peak of its mountain, with the leaf's index within the mountain as the path.
+/// peaks are all the peaks of the range, left to right.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MmrProof<F: PrimeField>
+{
+    pub index: usize,
+    pub num_leaves: usize,
+    pub path: MerklePath<F>,
+    pub peaks: Vec<F>,
+}
+
+impl<F: PrimeField> MmrProof<F>
+{
+    pub fn verify<LH: LeafHasher<F>, NH: NodeHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        root: F,
+        leaf: &[F]) -> bool
+    {
+        if self.index >= self.num_leaves || self.peaks.len() != self.num_leaves.count_ones() as usize
+        {
+            return false;
+        }
+        let height = mountain_height(self.index, self.num_leaves);
+        let local_index = self.index & ((1 << height) - 1);
+        if self.path.path.len() != height
+            || self.path.path.iter().enumerate().any(|(i, bit)| *bit != ((local_index >> (height - 1 - i)) & 1 == 1))
+        {
+            return false;
+        }
+        let peak = match self.path.compute_root(leaf_hasher, node_hasher, leaf) {
+            Ok(peak) => peak,
+            Err(_) => return false,
+        };
+        // Taller mountains are to the left, one for every bit of num_leaves above the leaf's mountain.
+        let position = (self.num_leaves >> (height + 1)).count_ones() as usize;
+        peak == self.peaks[position] && bag_peaks(node_hasher, self.num_leaves, &self.peaks) == root
+    }
+
+    /// The peaks indexed by height, with zero for the heights without a peak,
+    /// in the layout MmrGadget::verify takes for a range of mountains of height at most max_height.
+    pub fn peaks_by_height(&self, max_height: usize) -> Vec<F>
+    {
+        let mut peaks = vec![F::zero(); max_height + 1];
+        let mut position = 0;
+        for height in (0..=max_height).rev()
+        {
+            if (self.num_leaves >> height) & 1 == 1
+            {
+                peaks[height] = self.peaks[position];
+                position += 1;
+            }
+        }
+        peaks
+    }
+
+    /// The authentication path, padded at the front to max_height entries, as MmrGadget::verify takes it.
+    pub fn padded_auth_path(&self, max_height: usize) -> Vec<F>
+    {
+        let mut auth_path = vec![F::zero(); max_height - self.path.auth_path.len()];
+        auth_path.extend_from_slice(&self.path.auth_path);
+        auth_path
+    }
+}
+
+/// An append-only log committed to by a Merkle Mountain Range: a list of perfect binary trees
+/// of strictly decreasing heights, one for every set bit of the number of leaves, whose peaks are bagged into the root.
+pub struct MerkleMountainRange<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>>
+{
+    leaf_hasher: LH,
+    node_hasher: NH,
+    // layers[h] holds every node of height h, across all mountains, in order.
+    // Mountains are aligned, so the ancestor of leaf i at height h is layers[h][i >> h].
+    layers: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>> MerkleMountainRange<F, LH, NH>
+{
+    pub fn new(leaf_hasher: LH, node_hasher: NH) -> Self
+    {
+        MerkleMountainRange{leaf_hasher, node_hasher, layers: vec![Vec::new()]}
+    }
+
+    pub fn num_leaves(&self) -> usize
+    {
+        self.layers[0].len()
+    }
+
+    /// Appends a leaf, returning its index.
+    pub fn append(&mut self, leaf: &[F]) -> usize
+    {
+        let index = self.num_leaves();
+        self.layers[0].push(self.leaf_hasher.hash_leaf(leaf));
+        // Merge the two rightmost mountains for as long as they have the same height.
+        let mut height = 0;
+        while self.layers[height].len() % 2 == 0
+        {
+            let len = self.layers[height].len();
+            let parent = self.node_hasher.hash_node(self.layers[height][len - 2], self.layers[height][len - 1]);
+            if self.layers.len() == height + 1
+            {
+                self.layers.push(Vec::new());
+            }
+            self.layers[height + 1].push(parent);
+            height += 1;
+        }
+        index
+    }
+
+    /// The peaks, left to right.
+    pub fn peaks(&self) -> Vec<F>
+    {
+        (0..self.layers.len()).rev()
+            .filter(|height| self.layers[*height].len() % 2 == 1)
+            .map(|height| self.layers[height][self.layers[height].len() - 1])
+            .collect()
+    }
+
+    pub fn root(&self) -> F
+    {
+        bag_peaks(&self.node_hasher, self.num_leaves(), &self.peaks())
+    }
+
+    pub fn prove(&self, index: usize) -> Result<MmrProof<F>, Error>
+    {
+        let num_leaves = self.num_leaves();
+        if index >= num_leaves
+        {
+            return Err(Error::IndexOutOfBounds{index, len: num_leaves});
+        }
+        let height = mountain_height(index, num_leaves);
+        let mut path = vec![false; height];
+        let mut auth_path = vec![F::zero(); height];
+        for h in 0..height
+        {
+            path[height - 1 - h] = (index >> h) & 1 == 1;
+            auth_path[height - 1 - h] = self.layers[h][(index >> h) ^ 1];
+        }
+        Ok(MmrProof{index, num_leaves, path: MerklePath{path, auth_path}, peaks: self.peaks()})
+    }
+
+    pub fn verify(&self, root: F, proof: &MmrProof<F>, leaf: &[F]) -> bool
+    {
+        proof.verify(&self.leaf_hasher, &self.node_hasher, root, leaf)
+    }
+}
+
+/// Verifies MmrProofs in-circuit, for ranges whose mountains have height at most some max_height,
+/// i.e. with less than 2^(max_height + 1) leaves.
+/// The number of leaves and the index are variables, so one circuit handles every number of leaves.
+pub struct MmrGadget<LH, NH>
+{
+    pub leafHasher: LH,
+    pub nodeHasher: NH,
+}
+
+impl<LH, NH> MmrGadget<LH, NH>
+{
+    /// peaks and auth_path are laid out as MmrProof::peaks_by_height and MmrProof::padded_auth_path return them,
+    /// and max_height is auth_path.len().
+    pub fn verify<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        num_leaves: &FG,
+        index: &FG,
+        root: FG,
+        peaks: &[FG],
+        auth_path: &[FG],
+        leaf: &[FG]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let max_height = auth_path.len();
+        if peaks.len() != max_height + 1
+        {
+            return Err(Error::InvalidLength{expected: max_height + 1, found: peaks.len()});
+        }
+        // Both in big-endian, so the bit of height h is at max_height - h.
+        let num_leaves_bits = to_bits_fixed(&mut cs.ns(|| "num leaves bits"), num_leaves, max_height + 1)?;
+        let index_bits = to_bits_fixed(&mut cs.ns(|| "index bits"), index, max_height + 1)?;
+
+        // The leaf is in the mountain of the highest height at which index and num_leaves differ.
+        // Scanning from the top, is_mountain[h] is set at that height, and found is set if there is one, i.e. if index < num_leaves.
+        let mut is_mountain = vec![Boolean::constant(false); max_height + 1];
+        let mut prefix_equal = Boolean::constant(true);
+        let mut found = Boolean::constant(false);
+        for h in (0..=max_height).rev()
+        {
+            let mut cs_h = cs.ns(|| format!("find mountain {:?}", h));
+            let n_bit = &num_leaves_bits[max_height - h];
+            let i_bit = &index_bits[max_height - h];
+            let differs = Boolean::and(&mut cs_h.ns(|| "differs"), n_bit, &i_bit.not())?;
+            is_mountain[h] = Boolean::and(&mut cs_h.ns(|| "is mountain"), &prefix_equal, &differs)?;
+            found = Boolean::or(&mut cs_h.ns(|| "found"), &found, &is_mountain[h])?;
+            let bits_equal = Boolean::xor(&mut cs_h.ns(|| "bits differ"), n_bit, i_bit)?.not();
+            prefix_equal = Boolean::and(&mut cs_h.ns(|| "prefix equal"), &prefix_equal, &bits_equal)?;
+        }
+        found.enforce_equal(&mut cs.ns(|| "index less than num leaves"), &Boolean::constant(true))?;
+
+        // Hash up the whole padded path, and check the ancestor at the mountain's height against its peak.
+        let mut curHash = self.leafHasher.hash_leaf(&mut cs.ns(|| "leafhash"), leaf)?;
+        for h in 0..=max_height
+        {
+            let mut cs_h = cs.ns(|| format!("MT layer {:?}", h));
+            let selected = FG::conditionally_select(&mut cs_h.ns(|| "select ancestor"), &is_mountain[h], &curHash, &peaks[h])?;
+            selected.enforce_equal(&mut cs_h.ns(|| "peak check"), &peaks[h])?;
+            if h < max_height
+            {
+                let bit = index_bits[max_height - h];
+                let curVec = vec![curHash.clone(), auth_path[max_height - 1 - h].clone()];
+                let left_hash = mux(&mut cs_h.ns(|| "left mux"), &curVec, &[bit])?;
+                let right_hash = mux(&mut cs_h.ns(|| "right mux"), &curVec, &[bit.not()])?;
+                curHash = self.nodeHasher.hash_node(&mut cs_h.ns(|| "node hash"), left_hash, right_hash)?;
+            }
+        }
+
+        // Bag the peaks which are present from the lowest up, matching bag_peaks.
+        let mut bag = FG::zero(&mut cs.ns(|| "empty bag"))?;
+        let mut started = Boolean::constant(false);
+        for h in 0..=max_height
+        {
+            let mut cs_h = cs.ns(|| format!("bag peak {:?}", h));
+            let present = &num_leaves_bits[max_height - h];
+            let hashed = self.nodeHasher.hash_node(&mut cs_h.ns(|| "hash peak"), peaks[h].clone(), bag.clone())?;
+            let with_peak = FG::conditionally_select(&mut cs_h.ns(|| "first peak"), &started, &hashed, &peaks[h])?;
+            bag = FG::conditionally_select(&mut cs_h.ns(|| "add peak"), present, &with_peak, &bag)?;
+            started = Boolean::or(&mut cs_h.ns(|| "started"), &started, present)?;
+        }
+        let computed_root = self.nodeHasher.hash_node(&mut cs.ns(|| "bind num leaves"), num_leaves.clone(), bag)?;
+        computed_root.enforce_equal(&mut cs.ns(|| "root check"), &root)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::merkle_tree::{*, mmr::*};
+    use crate::hashing::{sponge::*, leafhash::*, two_to_one_hash::*, dummy_permutation::*};
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    type TestMmr = MerkleMountainRange<Fr, LeafHash<Fr, MixingDummyPermutation<Fr>>, TwoToOneHash<Fr, MixingDummyPermutation<Fr>>>;
+
+    fn new_mmr() -> Result<TestMmr, Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation);
+        Ok(MerkleMountainRange::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?))
+    }
+
+    #[test]
+    fn mmr_test() -> Result<(), Error> {
+        let mut mmr = new_mmr()?;
+        let leaves: Vec<Vec<Fr>> = (0..11u32).map(|i| vec![Fr::from(i), Fr::from(2 * i)]).collect();
+        let mut roots = vec![mmr.root()];
+        for i in 0..leaves.len() {
+            assert_eq!(mmr.append(&leaves[i]), i);
+            roots.push(mmr.root());
+        }
+        // 11 = 0b1011, so there are mountains of height 3, 1 and 0
+        assert_eq!(mmr.peaks().len(), 3);
+        assert_eq!(mmr.peaks()[2], mmr.leaf_hasher.hash(&leaves[10]));
+        for i in 0..roots.len() {
+            for j in 0..i {
+                assert_ne!(roots[i], roots[j]);
+            }
+        }
+
+        let root = mmr.root();
+        for i in 0..leaves.len() {
+            let proof = mmr.prove(i)?;
+            assert!(mmr.verify(root, &proof, &leaves[i]));
+            assert!(!mmr.verify(root, &proof, &leaves[(i + 1) % leaves.len()]));
+            assert!(!mmr.verify(roots[10], &proof, &leaves[i]));
+        }
+        match mmr.prove(11) {
+            Err(Error::IndexOutOfBounds{index: 11, len: 11}) => (),
+            _ => panic!("out of bounds index should fail"),
+        };
+        Ok(())
+    }
+
+    #[test]
+    fn mmr_gadget_test() -> Result<(), Error> {
+        let mut mmr = new_mmr()?;
+        let leaves: Vec<Vec<Fr>> = (0..11u32).map(|i| vec![Fr::from(i)]).collect();
+        for leaf in leaves.iter() {
+            mmr.append(leaf);
+        }
+        let max_height = 4;
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MMR_gadget = MmrGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let num_leaves = FrGadget::alloc_input(&mut cs.ns(|| "num leaves"), || Ok(Fr::from(11u32)))?;
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(mmr.root()))?;
+
+        // Leaves in the mountains of height 3, 1 and 0
+        for index in vec![2usize, 9, 10] {
+            let mut cs_i = cs.ns(|| format!("index {:?}", index));
+            let proof = mmr.prove(index)?;
+            let mut peaks = Vec::new();
+            for (h, peak) in proof.peaks_by_height(max_height).iter().enumerate() {
+                peaks.push(FrGadget::alloc(&mut cs_i.ns(|| format!("peak {:?}", h)), || Ok(*peak))?);
+            }
+            let mut auth_path = Vec::new();
+            for (i, node) in proof.padded_auth_path(max_height).iter().enumerate() {
+                auth_path.push(FrGadget::alloc(&mut cs_i.ns(|| format!("auth path {:?}", i)), || Ok(*node))?);
+            }
+            let leaf = vec![FrGadget::alloc(&mut cs_i.ns(|| "leaf"), || Ok(leaves[index][0]))?];
+            let index_gadget = FrGadget::alloc(&mut cs_i.ns(|| "index"), || Ok(Fr::from(index as u32)))?;
+            MMR_gadget.verify(&mut cs_i.ns(|| "MMR"), &num_leaves, &index_gadget, root.clone(), &peaks, &auth_path, &leaf)?;
+        }
+        assert!(cs.is_satisfied());
+
+        // An index past the end is rejected
+        let proof = mmr.prove(10)?;
+        let mut peaks = Vec::new();
+        for (h, peak) in proof.peaks_by_height(max_height).iter().enumerate() {
+            peaks.push(FrGadget::alloc(&mut cs.ns(|| format!("bad peak {:?}", h)), || Ok(*peak))?);
+        }
+        let mut auth_path = Vec::new();
+        for (i, node) in proof.padded_auth_path(max_height).iter().enumerate() {
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("bad auth path {:?}", i)), || Ok(*node))?);
+        }
+        let leaf = vec![FrGadget::alloc(&mut cs.ns(|| "bad leaf"), || Ok(leaves[10][0]))?];
+        let index_gadget = FrGadget::alloc(&mut cs.ns(|| "bad index"), || Ok(Fr::from(11u32)))?;
+        MMR_gadget.verify(&mut cs.ns(|| "bad MMR"), &num_leaves, &index_gadget, root, &peaks, &auth_path, &leaf)?;
+        assert!(!cs.is_satisfied());
+        Ok(())
+    }
+}
@@ -1,38 +1,239 @@
+pub mod kary;
+pub mod multiproof;
+pub mod sparse;
+pub mod incremental;
+pub mod mmr;
+pub mod cap;
+
 use algebra::fields::{PrimeField, Field};
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{prelude::*};
-use crate::hashing::{*, sponge::*, leafhash::*, two_to_one_hash::*};
-use crate::algebra::mux::*;
+use crate::hashing::*;
+use crate::algebra::{mux::*, equality::is_equal};
+use crate::Error;
+use rayon::prelude::*;
 
-// TODO: Make LeafHash and NodeHash traits
-pub struct MerklePathGadget<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
+/// An authentication path, in the layout MerklePathGadget::verify consumes.
+/// path is the leaf index in big-endian bits, so its last bit is index % 2.
+/// auth_path[i] is the sibling of the node on the path at the level path[i] selects,
+/// so auth_path[0] is a child of the root, and the last entry is the leaf's sibling.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerklePath<F: PrimeField>
 {
-    pub leafHasher: LeafHashGadget<F, FG, P>,
-    pub nodeHasher: TwoToOneHashGadget<F, FG, P>,
+    pub path: Vec<bool>,
+    pub auth_path: Vec<F>,
 }
 
-impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone> MerklePathGadget<F, FG, P>
+impl<F: PrimeField> MerklePath<F>
+{
+    /// Computes the root implied by this path and leaf.
+    pub fn compute_root<LH: LeafHasher<F>, NH: NodeHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        leaf: &[F]) -> Result<F, Error>
+    {
+        self.compute_root_from_leaf_hash(node_hasher, leaf_hasher.hash_leaf(leaf))
+    }
+
+    /// Like compute_root, for a leaf which is already hashed.
+    pub fn compute_root_from_leaf_hash<NH: NodeHasher<F>>(&self,
+        node_hasher: &NH,
+        leaf_hash: F) -> Result<F, Error>
+    {
+        if self.path.len() != self.auth_path.len()
+        {
+            return Err(Error::InvalidLength{expected: self.path.len(), found: self.auth_path.len()});
+        }
+        let mut cur_hash = leaf_hash;
+        // Traverse up the tree, so iterate over the path in reverse.
+        for i in (0..self.path.len()).rev()
+        {
+            cur_hash = if self.path[i] {
+                node_hasher.hash_node(self.auth_path[i], cur_hash)
+            } else {
+                node_hasher.hash_node(cur_hash, self.auth_path[i])
+            };
+        }
+        Ok(cur_hash)
+    }
+
+    pub fn verify<LH: LeafHasher<F>, NH: NodeHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        root: F,
+        leaf: &[F]) -> bool
+    {
+        match self.compute_root(leaf_hasher, node_hasher, leaf) {
+            Ok(computed_root) => computed_root == root,
+            Err(_) => false,
+        }
+    }
+}
+
+/// A binary Merkle tree over a power of two number of leaves, each leaf being a vector of field elements.
+pub struct MerkleTree<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>>
+{
+    leaf_hasher: LH,
+    node_hasher: NH,
+    // layers[0] holds the leaf hashes, and layers[depth] holds just the root.
+    layers: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField, LH: LeafHasher<F> + Sync, NH: NodeHasher<F> + Sync> MerkleTree<F, LH, NH>
+{
+    /// Builds the tree, hashing each layer in parallel.
+    pub fn new(leaf_hasher: LH, node_hasher: NH, leaves: &[Vec<F>]) -> Result<Self, Error>
+    {
+        let num_leaves = leaves.len();
+        if num_leaves == 0 || num_leaves & (num_leaves - 1) != 0
+        {
+            return Err(Error::NotPowerOfTwo(num_leaves));
+        }
+
+        let mut layers = vec![leaves.par_iter().map(|leaf| leaf_hasher.hash_leaf(leaf)).collect::<Vec<F>>()];
+        while layers[layers.len() - 1].len() > 1
+        {
+            let next_layer = layers[layers.len() - 1].par_chunks(2)
+                .map(|pair| node_hasher.hash_node(pair[0], pair[1]))
+                .collect();
+            layers.push(next_layer);
+        }
+
+        Ok(MerkleTree{leaf_hasher, node_hasher, layers})
+    }
+
+    pub fn root(&self) -> F
+    {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    pub fn depth(&self) -> usize
+    {
+        self.layers.len() - 1
+    }
+
+    pub fn num_leaves(&self) -> usize
+    {
+        self.layers[0].len()
+    }
+
+    pub fn generate_proof(&self, index: usize) -> Result<MerklePath<F>, Error>
+    {
+        if index >= self.num_leaves()
+        {
+            return Err(Error::IndexOutOfBounds{index, len: self.num_leaves()});
+        }
+        let depth = self.depth();
+        let mut path = vec![false; depth];
+        let mut auth_path = vec![F::zero(); depth];
+        // Walk up from the leaves, filling in the path from its end.
+        let mut cur_index = index;
+        for level in 0..depth
+        {
+            path[depth - 1 - level] = cur_index & 1 == 1;
+            auth_path[depth - 1 - level] = self.layers[level][cur_index ^ 1];
+            cur_index >>= 1;
+        }
+        Ok(MerklePath{path, auth_path})
+    }
+
+    pub fn verify(&self, root: F, proof: &MerklePath<F>, leaf: &[F]) -> bool
+    {
+        proof.verify(&self.leaf_hasher, &self.node_hasher, root, leaf)
+    }
+
+    /// Replaces the leaf at index, rehashing only its ancestors.
+    /// Returns the new root, and the leaf's path, which authenticates the old leaf against the old root
+    /// and the new leaf against the new root, as siblings are unchanged.
+    pub fn update(&mut self, index: usize, leaf: &[F]) -> Result<(F, MerklePath<F>), Error>
+    {
+        let proof = self.generate_proof(index)?;
+        self.layers[0][index] = self.leaf_hasher.hash_leaf(leaf);
+        let mut cur_index = index;
+        for level in 0..self.depth()
+        {
+            let left_index = cur_index & !1;
+            let parent = self.node_hasher.hash_node(self.layers[level][left_index], self.layers[level][left_index + 1]);
+            cur_index >>= 1;
+            self.layers[level + 1][cur_index] = parent;
+        }
+        Ok((self.root(), proof))
+    }
+}
+
+/// Verifies Merkle paths in-circuit, for any LeafHasherGadget and NodeHasherGadget.
+pub struct MerklePathGadget<LH, NH>
+{
+    pub leafHasher: LH,
+    pub nodeHasher: NH,
+}
+
+impl<LH, NH> MerklePathGadget<LH, NH>
 {
     // We don't use the Zexe API for MTs.
     // The Zexe API for an authentication path requires the caller to pass in both the left and right hash
     // at every level. This is both computationally wasteful, and a pain for the caller to use.
     // Furthermore it requires a boolean should_enforce, which is a concern that can be handled 
-    // at a higher level of abstraction.
+    // at a higher level of abstraction, using check or compute_root.
     //
-    // The path passed in should be the index of the leaf in the MT, represented in ?-endian bits. 
+    // The path passed in should be the index of the leaf in the MT, represented in big-endian bits. 
     // The trailing bit of the Path should be index % 2.
-    // TODO: Recall which endianness is which
     // 
-    // The authentication path should be given according to the same endianness.
+    // The authentication path should be given according to the same endianness, see MerklePath.
     //  <TODO: Add proper explanation and diagram>, import prior diagram from Tendermint
-    pub fn verify<CS: ConstraintSystem<F>>(&self, 
+    pub fn verify<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self, 
         mut cs: CS, 
         path: &[Boolean], 
         root: FG, 
         auth_path: &[FG], 
-        leaf: &[FG]) -> Result<(), SynthesisError>
+        leaf: &[FG]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let curHash = self.compute_root(&mut cs.ns(|| "compute root"), path, auth_path, leaf)?;
+        curHash.enforce_equal(&mut cs, &root)?;
+        Ok(())
+    }
+
+    /// Returns a Boolean which is true if and only if the path authenticates leaf against root.
+    /// Unlike verify, this doesn't enforce anything, so the caller can e.g. gate it on a selector bit.
+    pub fn check<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self, 
+        mut cs: CS, 
+        path: &[Boolean], 
+        root: &FG, 
+        auth_path: &[FG], 
+        leaf: &[FG]) -> Result<Boolean, Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let curHash = self.compute_root(&mut cs.ns(|| "compute root"), path, auth_path, leaf)?;
+        Ok(is_equal(&mut cs.ns(|| "root check"), &curHash, root)?)
+    }
+
+    /// Computes the root implied by the path and leaf, in the same layout as verify.
+    /// Given only the last n bits of a path and the last n entries of its authentication path,
+    /// this computes the leaf's ancestor n levels up instead.
+    pub fn compute_root<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self, 
+        mut cs: CS, 
+        path: &[Boolean], 
+        auth_path: &[FG], 
+        leaf: &[FG]) -> Result<FG, Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let leafHash = self.leafHasher.hash_leaf(&mut cs.ns(|| "leafhash"), leaf)?;
+        self.compute_root_from_leaf_hash(&mut cs, path, auth_path, leafHash)
+    }
+
+    /// Like compute_root, for a leaf which is already hashed.
+    pub fn compute_root_from_leaf_hash<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self, 
+        mut cs: CS, 
+        path: &[Boolean], 
+        auth_path: &[FG], 
+        leafHash: FG) -> Result<FG, Error>
+    where
+        NH: NodeHasherGadget<F, FG>
     {
-        let leafHash = self.leafHasher.hash(&mut cs.ns(|| "leafhash"), leaf)?;
         let mut curHash = leafHash;
 
         // To traverse up a MT, we iterate over the path in reverse.
@@ -40,7 +241,10 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
         rev_path.reverse();
         let mut auth_path_rev = auth_path.to_vec();
         auth_path_rev.reverse();
-        assert_eq!(auth_path_rev.len(), path.len());
+        if auth_path_rev.len() != path.len()
+        {
+            return Err(Error::InvalidLength{expected: path.len(), found: auth_path_rev.len()});
+        }
 
         for i in (0..auth_path_rev.len()) {
             let mut cs_i = cs.ns(|| format!("MT layer {:?}", i));
@@ -49,7 +253,7 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
             // and the bit being 1 indicates our currently hashed value is on the right.
             // so we set left_hash = mux(bit, [curHash, authPathHash]), and similarly,
             // right_hash = mux(not bit, [curHash, authPathHash])
-            // It is straight forward to generalize this to higher arity trees
+            // KaryMerklePathGadget generalizes this to higher arity trees
 
             let curVec = vec![curHash.clone(), auth_path_rev[i].clone()];
             let left_hash = mux(&mut cs_i.ns(|| "left mux"), 
@@ -57,10 +261,65 @@ impl<F: PrimeField, FG: FieldGadget<F, F>, P: PermutationGadget<F, FG> + Clone>
             let right_hash = mux(&mut cs_i.ns(|| "right mux"), 
                 &curVec, &[bit.not()])?;
 
-            curHash = self.nodeHasher.hash(&mut cs_i, left_hash, right_hash)?;
+            curHash = self.nodeHasher.hash_node(&mut cs_i, left_hash, right_hash)?;
         }
 
-        curHash.enforce_equal(&mut cs, &root)?;
+        Ok(curHash)
+    }
+
+    /// Computes the roots before and after replacing old_leaf by new_leaf, for one path and authentication path.
+    /// Both traversals use the same path bits and siblings, so the two roots differ in exactly that leaf.
+    /// Each level muxes only the left child of each hash, deriving the right child as curHash + sibling - left,
+    /// which halves the muxing cost of calling compute_root twice.
+    pub fn compute_update_roots<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self, 
+        mut cs: CS, 
+        path: &[Boolean], 
+        auth_path: &[FG], 
+        old_leaf: &[FG],
+        new_leaf: &[FG]) -> Result<(FG, FG), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        if auth_path.len() != path.len()
+        {
+            return Err(Error::InvalidLength{expected: path.len(), found: auth_path.len()});
+        }
+        let mut oldHash = self.leafHasher.hash_leaf(&mut cs.ns(|| "old leafhash"), old_leaf)?;
+        let mut newHash = self.leafHasher.hash_leaf(&mut cs.ns(|| "new leafhash"), new_leaf)?;
+
+        // To traverse up a MT, we iterate over the path in reverse.
+        for i in (0..path.len()).rev() {
+            let mut cs_i = cs.ns(|| format!("MT layer {:?}", i));
+            let bit = &path[i];
+            let sibling = &auth_path[i];
+
+            let old_left = FG::conditionally_select(&mut cs_i.ns(|| "old left mux"), bit, sibling, &oldHash)?;
+            let old_right = oldHash.add(&mut cs_i.ns(|| "old sum"), sibling)?.sub(&mut cs_i.ns(|| "old right"), &old_left)?;
+            oldHash = self.nodeHasher.hash_node(&mut cs_i.ns(|| "old node hash"), old_left, old_right)?;
+
+            let new_left = FG::conditionally_select(&mut cs_i.ns(|| "new left mux"), bit, sibling, &newHash)?;
+            let new_right = newHash.add(&mut cs_i.ns(|| "new sum"), sibling)?.sub(&mut cs_i.ns(|| "new right"), &new_left)?;
+            newHash = self.nodeHasher.hash_node(&mut cs_i.ns(|| "new node hash"), new_left, new_right)?;
+        }
+
+        Ok((oldHash, newHash))
+    }
+
+    /// Enforces that replacing old_leaf by new_leaf at the position given by path turns old_root into new_root.
+    pub fn update<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self, 
+        mut cs: CS, 
+        path: &[Boolean], 
+        old_root: FG, 
+        new_root: FG, 
+        auth_path: &[FG], 
+        old_leaf: &[FG],
+        new_leaf: &[FG]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let (oldHash, newHash) = self.compute_update_roots(&mut cs.ns(|| "compute roots"), path, auth_path, old_leaf, new_leaf)?;
+        oldHash.enforce_equal(&mut cs.ns(|| "old root check"), &old_root)?;
+        newHash.enforce_equal(&mut cs.ns(|| "new root check"), &new_root)?;
         Ok(())
     }
 }
@@ -71,7 +330,7 @@ mod test {
     use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
     use r1cs_core::ConstraintSystem;
     use crate::merkle_tree::*;
-    use crate::hashing::{two_to_one_hash::*, leafhash::*, dummy_permutation::*};
+    use crate::hashing::{*, sponge::*, two_to_one_hash::*, leafhash::*, dummy_permutation::*};
     use crate::algebra::{domain::Domain, mux::*, polynomial::DensePolynomial};
 
     use algebra::{FpParameters, prelude::*};
@@ -82,9 +341,10 @@ mod test {
     use crate::alt_bn128::fr_gadget::FrGadget;
     use crate::alt_bn128::fr::Fr;
     use std::str::FromStr;
+    use crate::Error;
 
     #[test]
-    fn mt_test() -> Result<(), SynthesisError> {
+    fn mt_test() -> Result<(), Error> {
         let mut cs = TestConstraintSystem::<Fr>::new();
         let mut leaf_permutation = SeededDummyPermutation{seed: Fr::one()};
         let mut two_to_one_permutation = SeededDummyPermutation{seed: Fr::one() + Fr::one()};
@@ -95,8 +355,8 @@ mod test {
         let nodesponge = AlgebraicSpongeGadget::<Fr, FrGadget, SeededDummyPermutation<Fr>>::
             new(cs.ns(|| "2"), 1, 1, two_to_one_permutation)?; 
 
-        let leaf_hash = LeafHashGadget::new(&mut cs, leafsponge);
-        let node_hash = TwoToOneHashGadget::new(&mut cs, nodesponge);
+        let leaf_hash = LeafHashGadget::new(&mut cs, leafsponge)?;
+        let node_hash = TwoToOneHashGadget::new(&mut cs, nodesponge)?;
 
         // MT of {1, 2, 3, 4}
         let one = FrGadget::alloc(&mut cs.ns(|| format!("generate_{:?}", 1)), || Ok(Fr::from(1u32)))?;
@@ -118,4 +378,267 @@ mod test {
         assert!(cs.is_satisfied());
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn mt_invalid_path_length_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let permutation = SeededDummyPermutation{seed: Fr::one()};
+        let sponge = AlgebraicSpongeGadget::<Fr, FrGadget, SeededDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 1, 1, permutation)?;
+        let leaf_hash = LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge.clone())?;
+        let node_hash = TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge)?;
+        let MT_path_gadget = MerklePathGadget{leafHasher: leaf_hash, nodeHasher: node_hash};
+
+        let one = FrGadget::alloc(&mut cs.ns(|| "one"), || Ok(Fr::one()))?;
+        let res = MT_path_gadget.verify(&mut cs.ns(|| "MT"),
+            &[Boolean::constant(false), Boolean::constant(true)], one.clone(), &[one.clone()], &[one]);
+        match res {
+            Err(Error::InvalidLength{expected: 2, found: 1}) => (),
+            _ => panic!("mismatched path lengths should fail"),
+        };
+        Ok(())
+    }
+
+    #[test]
+    fn leaf_node_domain_separation_test() -> Result<(), Error> {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let a = Fr::from(1u32);
+        let b = Fr::from(2u32);
+
+        // Without domain separation, a leaf of two elements hashes exactly like an inner node
+        let mut raw_sponge = sponge.clone();
+        raw_sponge.absorb(&[a, b]);
+        let raw_hash = raw_sponge.squeeze(1)[0];
+
+        let leaf_hash = LeafHash::new(sponge.clone())?.hash(&[a, b]);
+        let node_hash = TwoToOneHash::new(sponge.clone())?.hash(a, b);
+        let node_hash_depth_3 = TwoToOneHash::new_with_tree_depth(sponge.clone(), 3)?.hash(a, b);
+        let node_hash_depth_4 = TwoToOneHash::new_with_tree_depth(sponge, 4)?.hash(a, b);
+        assert_ne!(leaf_hash, node_hash);
+        assert_ne!(leaf_hash, raw_hash);
+        assert_ne!(node_hash, raw_hash);
+        assert_ne!(node_hash_depth_3, node_hash_depth_4);
+
+        let a_gadget = FrGadget::alloc(&mut cs.ns(|| "a"), || Ok(a))?;
+        let b_gadget = FrGadget::alloc(&mut cs.ns(|| "b"), || Ok(b))?;
+        let leaf_hasher = LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?;
+        let node_hasher = TwoToOneHashGadget::new_with_tree_depth(&mut cs.ns(|| "node hasher"), sponge_gadget, 3)?;
+        let leaf_hash_gadget = leaf_hasher.hash(&mut cs.ns(|| "leaf hash"), &[a_gadget.clone(), b_gadget.clone()])?;
+        let node_hash_gadget = node_hasher.hash(&mut cs.ns(|| "node hash"), a_gadget, b_gadget)?;
+        assert_eq!(leaf_hash_gadget.get_value().unwrap(), leaf_hash);
+        assert_eq!(node_hash_gadget.get_value().unwrap(), node_hash_depth_3);
+
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn native_mt_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let leaf_hash = LeafHash::new(sponge.clone())?;
+        let node_hash = TwoToOneHash::new(sponge.clone())?;
+        let leaves: Vec<Vec<Fr>> = (0..8u32).map(|i| vec![Fr::from(i), Fr::from(2 * i)]).collect();
+        let tree = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge.clone())?, &leaves)?;
+        assert_eq!(tree.depth(), 3);
+
+        let expected_root = node_hash.hash(
+            node_hash.hash(
+                node_hash.hash(leaf_hash.hash(&leaves[0]), leaf_hash.hash(&leaves[1])),
+                node_hash.hash(leaf_hash.hash(&leaves[2]), leaf_hash.hash(&leaves[3]))),
+            node_hash.hash(
+                node_hash.hash(leaf_hash.hash(&leaves[4]), leaf_hash.hash(&leaves[5])),
+                node_hash.hash(leaf_hash.hash(&leaves[6]), leaf_hash.hash(&leaves[7]))));
+        assert_eq!(tree.root(), expected_root);
+
+        for i in 0..8 {
+            let proof = tree.generate_proof(i)?;
+            assert!(tree.verify(tree.root(), &proof, &leaves[i]));
+            assert!(!tree.verify(tree.root(), &proof, &leaves[(i + 1) % 8]));
+        }
+        match tree.generate_proof(8) {
+            Err(Error::IndexOutOfBounds{index: 8, len: 8}) => (),
+            _ => panic!("out of bounds index should fail"),
+        };
+        match MerkleTree::new(leaf_hash, node_hash, &leaves[0..3]) {
+            Err(Error::NotPowerOfTwo(3)) => (),
+            _ => panic!("3 leaves should fail"),
+        };
+
+        // The native paths are in the layout the gadget consumes
+        let index = 6;
+        let proof = tree.generate_proof(index)?;
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let mut path = Vec::new();
+        let mut auth_path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let mut leaf = Vec::new();
+        for i in 0..leaves[index].len() {
+            leaf.push(FrGadget::alloc(&mut cs.ns(|| format!("leaf {:?}", i)), || Ok(leaves[index][i]))?);
+        }
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        MT_path_gadget.verify(&mut cs.ns(|| "MT"), &path, root, &auth_path, &leaf)?;
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    // A hasher that isn't built on a sponge, to check the tree only relies on the hasher traits.
+    // It is not collision resistant.
+    struct LinearHasher;
+
+    impl LeafHasher<Fr> for LinearHasher {
+        fn hash_leaf(&self, leaf: &[Fr]) -> Fr {
+            leaf.iter().fold(Fr::one(), |acc, x| acc + x)
+        }
+    }
+
+    impl NodeHasher<Fr> for LinearHasher {
+        fn hash_node(&self, left: Fr, right: Fr) -> Fr {
+            left + right.double()
+        }
+    }
+
+    impl LeafHasherGadget<Fr, FrGadget> for LinearHasher {
+        fn hash_leaf<CS: ConstraintSystem<Fr>>(&self, mut cs: CS, leaf: &[FrGadget]) -> Result<FrGadget, SynthesisError> {
+            let mut acc = FrGadget::one(&mut cs.ns(|| "one"))?;
+            for (i, x) in leaf.iter().enumerate() {
+                acc = acc.add(&mut cs.ns(|| format!("add {:?}", i)), x)?;
+            }
+            Ok(acc)
+        }
+    }
+
+    impl NodeHasherGadget<Fr, FrGadget> for LinearHasher {
+        fn hash_node<CS: ConstraintSystem<Fr>>(&self, mut cs: CS, left: FrGadget, right: FrGadget) -> Result<FrGadget, SynthesisError> {
+            let right_doubled = right.double(&mut cs.ns(|| "double"))?;
+            left.add(&mut cs.ns(|| "add"), &right_doubled)
+        }
+    }
+
+    #[test]
+    fn custom_hasher_mt_test() -> Result<(), Error> {
+        let leaves: Vec<Vec<Fr>> = (0..4u32).map(|i| vec![Fr::from(i)]).collect();
+        let tree = MerkleTree::new(LinearHasher, LinearHasher, &leaves)?;
+        // Leaves hash to 1, 2, 3, 4, so the root is (1 + 2 * 2) + 2 * (3 + 2 * 4)
+        assert_eq!(tree.root(), Fr::from(27u32));
+
+        let index = 2;
+        let proof = tree.generate_proof(index)?;
+        assert!(tree.verify(tree.root(), &proof, &leaves[index]));
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let MT_path_gadget = MerklePathGadget{leafHasher: LinearHasher, nodeHasher: LinearHasher};
+        let mut path = Vec::new();
+        let mut auth_path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let leaf = FrGadget::alloc(&mut cs.ns(|| "leaf"), || Ok(leaves[index][0]))?;
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        MT_path_gadget.verify(&mut cs.ns(|| "MT"), &path, root, &auth_path, &[leaf])?;
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn mt_check_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let leaves: Vec<Vec<Fr>> = (0..4u32).map(|i| vec![Fr::from(i)]).collect();
+        let tree = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?, &leaves)?;
+        let index = 1;
+        let proof = tree.generate_proof(index)?;
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let mut path = Vec::new();
+        let mut auth_path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let leaf = vec![FrGadget::alloc(&mut cs.ns(|| "leaf"), || Ok(leaves[index][0]))?];
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        let wrong_root = FrGadget::alloc(&mut cs.ns(|| "wrong root"), || Ok(tree.root() + Fr::one()))?;
+
+        let computed_root = MT_path_gadget.compute_root(&mut cs.ns(|| "compute root"), &path, &auth_path, &leaf)?;
+        assert_eq!(computed_root.get_value().unwrap(), tree.root());
+        let valid = MT_path_gadget.check(&mut cs.ns(|| "check root"), &path, &root, &auth_path, &leaf)?;
+        let invalid = MT_path_gadget.check(&mut cs.ns(|| "check wrong root"), &path, &wrong_root, &auth_path, &leaf)?;
+        assert!(valid.get_value().unwrap());
+        assert!(!invalid.get_value().unwrap());
+        // A failed check doesn't make the constraint system unsatisfiable
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn mt_update_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let mut leaves: Vec<Vec<Fr>> = (0..8u32).map(|i| vec![Fr::from(i), Fr::from(i + 10)]).collect();
+        let mut tree = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge.clone())?, &leaves)?;
+        let old_root = tree.root();
+        let index = 5;
+        let old_leaf = leaves[index].clone();
+        let new_leaf = vec![Fr::from(100u32), Fr::from(200u32)];
+
+        let (new_root, proof) = tree.update(index, &new_leaf)?;
+        leaves[index] = new_leaf.clone();
+        let rebuilt = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?, &leaves)?;
+        assert_eq!(new_root, rebuilt.root());
+        assert_eq!(tree.root(), new_root);
+        assert!(tree.verify(old_root, &proof, &old_leaf));
+        assert!(tree.verify(new_root, &proof, &new_leaf));
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let mut path = Vec::new();
+        let mut auth_path = Vec::new();
+        for i in 0..proof.path.len() {
+            path.push(Boolean::alloc(&mut cs.ns(|| format!("path bit {:?}", i)), || Ok(proof.path[i]))?);
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let mut old_leaf_gadget = Vec::new();
+        let mut new_leaf_gadget = Vec::new();
+        for i in 0..old_leaf.len() {
+            old_leaf_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("old leaf {:?}", i)), || Ok(old_leaf[i]))?);
+            new_leaf_gadget.push(FrGadget::alloc(&mut cs.ns(|| format!("new leaf {:?}", i)), || Ok(new_leaf[i]))?);
+        }
+        let old_root_gadget = FrGadget::alloc(&mut cs.ns(|| "old root"), || Ok(old_root))?;
+        let new_root_gadget = FrGadget::alloc(&mut cs.ns(|| "new root"), || Ok(new_root))?;
+        MT_path_gadget.update(&mut cs.ns(|| "update"), &path,
+            old_root_gadget.clone(), new_root_gadget, &auth_path, &old_leaf_gadget, &new_leaf_gadget)?;
+        assert!(cs.is_satisfied());
+
+        // Claiming the root is unchanged is rejected
+        MT_path_gadget.update(&mut cs.ns(|| "bad update"), &path,
+            old_root_gadget.clone(), old_root_gadget, &auth_path, &old_leaf_gadget, &new_leaf_gadget)?;
+        assert!(!cs.is_satisfied());
+        Ok(())
+    }
+}
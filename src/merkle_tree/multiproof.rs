@@ -0,0 +1,390 @@
+//! Merkle multi-proofs, which open several leaves against one root.
+//!
+//! With witness indices this is only half of what was asked for. `MerklePathGadget::verify_batch`
+//! hashes the top c layers once, from a shared Merkle cap, but it does not deduplicate witness indices:
+//! below the cap every query hashes its full path, even when queries repeat or share nodes.
+//! Deduplicating there would need the queries sorted in-circuit, plus a selection of which nodes to reuse,
+//! and the circuit would still have to hash the worst case, so it is out of scope here.
+//! Only `MerklePathGadget::verify_multi_proof`, whose indices are constants, hashes every internal node once.
+
+use algebra::fields::PrimeField;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{prelude::*};
+use crate::hashing::*;
+use crate::algebra::mux::*;
+use crate::merkle_tree::*;
+use crate::Error;
+
+fn check_indices(indices: &[usize], depth: usize) -> Result<(), Error>
+{
+    if indices.is_empty()
+    {
+        return Err(Error::InvalidLength{expected: 1, found: 0});
+    }
+    // The depth may come from an untrusted proof, so it is checked before shifting by it
+    let max_depth = 8 * std::mem::size_of::<usize>() - 1;
+    if depth > max_depth
+    {
+        return Err(Error::TreeTooDeep{depth, max_depth});
+    }
+    let num_leaves = 1 << depth;
+    for i in 0..indices.len()
+    {
+        if indices[i] >= num_leaves
+        {
+            return Err(Error::IndexOutOfBounds{index: indices[i], len: num_leaves});
+        }
+        if i > 0 && indices[i] <= indices[i - 1]
+        {
+            return Err(Error::UnsortedIndices);
+        }
+    }
+    Ok(())
+}
+
+// Walks a multi-proof up the tree one level at a time, where level 0 holds the given leaves.
+// A node needs its sibling from the proof exactly when that sibling is not itself on one of the paths,
+// and then sibling(level, index in level) supplies it. Siblings are requested in the order the proof stores them.
+// Each parent is hash(level, node, left, right), where node counts the parents computed so far in the level.
+fn walk_multi_proof<T, S, H>(
+    indices: &[usize],
+    depth: usize,
+    leaves: Vec<T>,
+    mut sibling: S,
+    mut hash: H) -> Result<T, Error>
+where
+    T: Clone,
+    S: FnMut(usize, usize) -> Result<T, Error>,
+    H: FnMut(usize, usize, T, T) -> Result<T, Error>
+{
+    check_indices(indices, depth)?;
+    if leaves.len() != indices.len()
+    {
+        return Err(Error::InvalidLength{expected: indices.len(), found: leaves.len()});
+    }
+
+    let mut known: Vec<(usize, T)> = indices.iter().cloned().zip(leaves.into_iter()).collect();
+    for level in 0..depth
+    {
+        let mut next = Vec::with_capacity(known.len());
+        let mut i = 0;
+        while i < known.len()
+        {
+            let index = known[i].0;
+            let parent = if index % 2 == 0 && i + 1 < known.len() && known[i + 1].0 == index + 1 {
+                let parent = hash(level, next.len(), known[i].1.clone(), known[i + 1].1.clone())?;
+                i += 2;
+                parent
+            } else {
+                let other = sibling(level, index ^ 1)?;
+                let parent = if index % 2 == 0 {
+                    hash(level, next.len(), known[i].1.clone(), other)?
+                } else {
+                    hash(level, next.len(), other, known[i].1.clone())?
+                };
+                i += 1;
+                parent
+            };
+            next.push((index / 2, parent));
+        }
+        known = next;
+    }
+    // The indices are distinct and below 2^depth, so they all meet at the root
+    Ok(known.swap_remove(0).1)
+}
+
+// Lists the positions of the siblings a multi-proof for the given indices consists of, as (level, index in level)
+// in the order they are consumed.
+fn auth_node_positions(indices: &[usize], depth: usize) -> Result<Vec<(usize, usize)>, Error>
+{
+    let mut positions = Vec::new();
+    walk_multi_proof(indices, depth, vec![(); indices.len()],
+        |level, index| { positions.push((level, index)); Ok(()) },
+        |_, _, _, _| Ok(()))?;
+    Ok(positions)
+}
+
+// Hands out the auth nodes of a multi-proof in order, failing rather than panicking if they run out.
+fn next_auth_node<'a, T: 'a + Clone, I: Iterator<Item = &'a T>>(auth_nodes: &mut I, expected: usize, found: usize) -> Result<T, Error>
+{
+    auth_nodes.next().cloned().ok_or(Error::InvalidLength{expected, found})
+}
+
+/// Opens several leaves of a binary Merkle tree at once.
+/// Siblings which are shared between the paths, or which are themselves on another path, are left out,
+/// so every node of the tree appears at most once.
+/// indices are strictly increasing, and the leaves passed to verify are in the same order.
+/// auth_nodes are the remaining siblings, ordered from the leaves up, and left to right within a level.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleMultiProof<F: PrimeField>
+{
+    pub indices: Vec<usize>,
+    pub depth: usize,
+    pub auth_nodes: Vec<F>,
+}
+
+impl<F: PrimeField> MerkleMultiProof<F>
+{
+    /// Computes the root implied by this proof and leaves.
+    pub fn compute_root<LH: LeafHasher<F>, NH: NodeHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        leaves: &[Vec<F>]) -> Result<F, Error>
+    {
+        let positions = auth_node_positions(&self.indices, self.depth)?;
+        if positions.len() != self.auth_nodes.len()
+        {
+            return Err(Error::InvalidLength{expected: positions.len(), found: self.auth_nodes.len()});
+        }
+        if leaves.len() != self.indices.len()
+        {
+            return Err(Error::InvalidLength{expected: self.indices.len(), found: leaves.len()});
+        }
+
+        let leaf_hashes = leaves.iter().map(|leaf| leaf_hasher.hash_leaf(leaf)).collect();
+        let mut auth_nodes = self.auth_nodes.iter();
+        walk_multi_proof(&self.indices, self.depth, leaf_hashes,
+            |_, _| next_auth_node(&mut auth_nodes, positions.len(), self.auth_nodes.len()),
+            |_, _, left, right| Ok(node_hasher.hash_node(left, right)))
+    }
+
+    pub fn verify<LH: LeafHasher<F>, NH: NodeHasher<F>>(&self,
+        leaf_hasher: &LH,
+        node_hasher: &NH,
+        root: F,
+        leaves: &[Vec<F>]) -> bool
+    {
+        match self.compute_root(leaf_hasher, node_hasher, leaves) {
+            Ok(computed_root) => computed_root == root,
+            Err(_) => false,
+        }
+    }
+}
+
+/// The witness for MerklePathGadget::verify_batch, for a given shared height c.
+/// shared_layer is the Merkle cap of height c, and auth_paths[i] is the MerkleCapPath auth_path of the i-th queried leaf.
+/// Unlike MerkleMultiProof, nothing below the shared layer is deduplicated: every auth path is complete,
+/// even for repeated indices or paths which share siblings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleBatchProof<F: PrimeField>
+{
+    pub shared_layer: Vec<F>,
+    pub auth_paths: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField, LH: LeafHasher<F> + Sync, NH: NodeHasher<F> + Sync> MerkleTree<F, LH, NH>
+{
+    /// The indices may be given in any order, and may repeat. The proof opens them sorted and deduplicated.
+    pub fn generate_multi_proof(&self, indices: &[usize]) -> Result<MerkleMultiProof<F>, Error>
+    {
+        let mut sorted_indices = indices.to_vec();
+        sorted_indices.sort();
+        sorted_indices.dedup();
+        let positions = auth_node_positions(&sorted_indices, self.depth())?;
+        let auth_nodes = positions.iter().map(|(level, index)| self.layers[*level][*index]).collect();
+        Ok(MerkleMultiProof{indices: sorted_indices, depth: self.depth(), auth_nodes})
+    }
+
+    /// See `MerkleBatchProof`. A shared height of about log2(indices.len()) minimizes the number of hashes.
+    pub fn generate_batch_proof(&self, indices: &[usize], shared_height: usize) -> Result<MerkleBatchProof<F>, Error>
+    {
+        let shared_layer = self.cap(shared_height)?;
+        let mut auth_paths = Vec::with_capacity(indices.len());
+        for index in indices
+        {
+            auth_paths.push(self.generate_cap_proof(*index, shared_height)?.auth_path);
+        }
+        Ok(MerkleBatchProof{shared_layer, auth_paths})
+    }
+}
+
+impl<LH, NH> MerklePathGadget<LH, NH>
+{
+    /// Verifies a MerkleMultiProof, for indices known at synthesis time.
+    /// The proof's structure is fixed by the indices, so every internal node is hashed exactly once.
+    pub fn verify_multi_proof<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        indices: &[usize],
+        depth: usize,
+        root: FG,
+        auth_nodes: &[FG],
+        leaves: &[Vec<FG>]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let positions = auth_node_positions(indices, depth)?;
+        if positions.len() != auth_nodes.len()
+        {
+            return Err(Error::InvalidLength{expected: positions.len(), found: auth_nodes.len()});
+        }
+        if leaves.len() != indices.len()
+        {
+            return Err(Error::InvalidLength{expected: indices.len(), found: leaves.len()});
+        }
+
+        let mut leaf_hashes = Vec::with_capacity(indices.len());
+        for (i, leaf) in leaves.iter().enumerate()
+        {
+            leaf_hashes.push(self.leafHasher.hash_leaf(&mut cs.ns(|| format!("leafhash {:?}", i)), leaf)?);
+        }
+        let num_auth_nodes = auth_nodes.len();
+        let mut auth_nodes = auth_nodes.iter();
+        let computed_root = walk_multi_proof(indices, depth, leaf_hashes,
+            |_, _| next_auth_node(&mut auth_nodes, positions.len(), num_auth_nodes),
+            |level, node, left, right| Ok(self.nodeHasher.hash_node(&mut cs.ns(|| format!("MT layer {:?} node {:?}", level, node)), left, right)?))?;
+
+        computed_root.enforce_equal(&mut cs.ns(|| "root check"), &root)?;
+        Ok(())
+    }
+
+    /// Verifies a batch of openings whose indices are witnesses, given as big-endian bits as in verify.
+    /// The top c layers are hashed once from shared_layer, and each path ends by selecting its ancestor there
+    /// by its first c bits. Witness indices are not deduplicated: below the shared layer every path is hashed
+    /// in full, so repeated indices and shared siblings are paid for once per path.
+    /// See `MerkleBatchProof` for the layout of shared_layer and auth_paths.
+    pub fn verify_batch<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        paths: &[Vec<Boolean>],
+        root: FG,
+        auth_paths: &[Vec<FG>],
+        shared_layer: &[FG],
+        leaves: &[Vec<FG>]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        if auth_paths.len() != paths.len()
+        {
+            return Err(Error::InvalidLength{expected: paths.len(), found: auth_paths.len()});
+        }
+        if leaves.len() != paths.len()
+        {
+            return Err(Error::InvalidLength{expected: paths.len(), found: leaves.len()});
+        }
+
+        // The shared layer is a Merkle cap, which is hashed up to the root once.
+        let shared_root = self.compute_root_from_cap(&mut cs.ns(|| "shared layer"), shared_layer)?;
+        shared_root.enforce_equal(&mut cs.ns(|| "root check"), &root)?;
+
+        for q in 0..paths.len()
+        {
+            self.verify_with_cap(&mut cs.ns(|| format!("query {:?}", q)), &paths[q], shared_layer, &auth_paths[q], &leaves[q])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::merkle_tree::{*, multiproof::*};
+    use crate::hashing::{sponge::*, leafhash::*, two_to_one_hash::*, dummy_permutation::*};
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    fn alloc_vec<CS: ConstraintSystem<Fr>>(mut cs: CS, values: &[Fr]) -> Result<Vec<FrGadget>, Error> {
+        let mut res = Vec::new();
+        for i in 0..values.len() {
+            res.push(FrGadget::alloc(&mut cs.ns(|| format!("elem {:?}", i)), || Ok(values[i]))?);
+        }
+        Ok(res)
+    }
+
+    #[test]
+    fn multi_proof_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let leaves: Vec<Vec<Fr>> = (0..16u32).map(|i| vec![Fr::from(i), Fr::from(i * i)]).collect();
+        let tree = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge.clone())?, &leaves)?;
+        let leaf_hash = LeafHash::new(sponge.clone())?;
+        let node_hash = TwoToOneHash::new(sponge)?;
+
+        let proof = tree.generate_multi_proof(&[9, 2, 3, 15, 1, 9])?;
+        assert_eq!(proof.indices, vec![1, 2, 3, 9, 15]);
+        // Leaves 0, 8 and 14, then nodes 5 and 6 of the level above, then node 1 of the level above that
+        assert_eq!(proof.auth_nodes.len(), 6);
+        let opened: Vec<Vec<Fr>> = proof.indices.iter().map(|i| leaves[*i].clone()).collect();
+        assert!(proof.verify(&leaf_hash, &node_hash, tree.root(), &opened));
+        let mut tampered = opened.clone();
+        tampered[2][0] += Fr::one();
+        assert!(!proof.verify(&leaf_hash, &node_hash, tree.root(), &tampered));
+        let mut unsorted = proof.clone();
+        unsorted.indices = vec![1, 2, 3, 3, 15];
+        match unsorted.compute_root(&leaf_hash, &node_hash, &opened) {
+            Err(Error::UnsortedIndices) => (),
+            _ => panic!("repeated indices should fail"),
+        };
+        let mut too_deep = proof.clone();
+        too_deep.depth = 8 * std::mem::size_of::<usize>();
+        assert!(too_deep.compute_root(&leaf_hash, &node_hash, &opened).is_err());
+        let mut too_few_nodes = proof.clone();
+        too_few_nodes.auth_nodes.pop();
+        assert!(too_few_nodes.compute_root(&leaf_hash, &node_hash, &opened).is_err());
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let auth_nodes = alloc_vec(&mut cs.ns(|| "auth nodes"), &proof.auth_nodes)?;
+        let mut leaves_gadget = Vec::new();
+        for i in 0..opened.len() {
+            leaves_gadget.push(alloc_vec(&mut cs.ns(|| format!("leaf {:?}", i)), &opened[i])?);
+        }
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        MT_path_gadget.verify_multi_proof(&mut cs.ns(|| "multi proof"),
+            &proof.indices, proof.depth, root, &auth_nodes, &leaves_gadget)?;
+        assert!(cs.is_satisfied());
+        Ok(())
+    }
+
+    #[test]
+    fn batch_proof_test() -> Result<(), Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation.clone());
+        let leaves: Vec<Vec<Fr>> = (0..16u32).map(|i| vec![Fr::from(i), Fr::from(i * i)]).collect();
+        let tree = MerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?, &leaves)?;
+        let indices = vec![13, 2, 13, 7];
+        let proof = tree.generate_batch_proof(&indices, 2)?;
+        assert_eq!(proof.shared_layer.len(), 4);
+        assert_eq!(proof.auth_paths[0].len(), 2);
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let MT_path_gadget = MerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let mut paths = Vec::new();
+        let mut auth_paths = Vec::new();
+        let mut leaves_gadget = Vec::new();
+        for (q, index) in indices.iter().enumerate() {
+            let mut path = Vec::new();
+            for (i, bit) in tree.generate_proof(*index)?.path.iter().enumerate() {
+                path.push(Boolean::alloc(&mut cs.ns(|| format!("query {:?} bit {:?}", q, i)), || Ok(*bit))?);
+            }
+            paths.push(path);
+            auth_paths.push(alloc_vec(&mut cs.ns(|| format!("auth path {:?}", q)), &proof.auth_paths[q])?);
+            leaves_gadget.push(alloc_vec(&mut cs.ns(|| format!("leaf {:?}", q)), &leaves[*index])?);
+        }
+        let shared_layer = alloc_vec(&mut cs.ns(|| "shared layer"), &proof.shared_layer)?;
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+        MT_path_gadget.verify_batch(&mut cs.ns(|| "batch"),
+            &paths, root.clone(), &auth_paths, &shared_layer, &leaves_gadget)?;
+        assert!(cs.is_satisfied());
+
+        // A shared layer which doesn't hash to the root is rejected
+        let mut bad_layer = proof.shared_layer.clone();
+        bad_layer[3] += Fr::one();
+        let bad_layer = alloc_vec(&mut cs.ns(|| "bad shared layer"), &bad_layer)?;
+        MT_path_gadget.verify_batch(&mut cs.ns(|| "bad batch"),
+            &paths, root, &auth_paths, &bad_layer, &leaves_gadget)?;
+        assert!(!cs.is_satisfied());
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use algebra::{prelude::*, biginteger::BigInteger, FpParameters};
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{prelude::*};
+use crate::hashing::*;
+use crate::merkle_tree::*;
+use crate::Error;
+use std::collections::BTreeMap;
+
+/// The path of a key in a SparseMerkleTree, its `MODULUS_BITS` bits in big-endian order.
+/// This is the decomposition `ToBitsGadget::to_bits_strict` produces in-circuit.
+pub fn key_to_path<F: PrimeField>(key: &F) -> Vec<bool>
+{
+    let num_bits = F::Params::MODULUS_BITS as usize;
+    // BigInteger::to_bits is big-endian, so the leading zeros of the representation come first
+    let repr_bits = key.into_repr().to_bits();
+    repr_bits[(repr_bits.len() - num_bits)..].to_vec()
+}
+
+/// A Merkle tree with a leaf for every field element, mapping keys to values.
+/// The leaf of a key holding value v is hash_leaf(&[v]), and the leaf of an absent key is zero.
+/// Every subtree without any present keys has a default hash, which is precomputed per height,
+/// so only the nodes with a present key below them are stored.
+pub struct SparseMerkleTree<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>>
+{
+    leaf_hasher: LH,
+    node_hasher: NH,
+    // defaults[h] is the hash of an empty subtree of height h, so defaults[0] is the empty leaf.
+    defaults: Vec<F>,
+    // Non-default nodes, keyed by (height, index in level). The index of the node at height h above a key's leaf
+    // is the key shifted right by h, so the root is (depth, 0).
+    nodes: BTreeMap<(usize, F::BigInt), F>,
+    values: BTreeMap<F::BigInt, F>,
+}
+
+impl<F: PrimeField, LH: LeafHasher<F>, NH: NodeHasher<F>> SparseMerkleTree<F, LH, NH>
+{
+    pub fn new(leaf_hasher: LH, node_hasher: NH) -> Self
+    {
+        let depth = F::Params::MODULUS_BITS as usize;
+        let mut defaults = vec![F::zero()];
+        for h in 0..depth
+        {
+            defaults.push(node_hasher.hash_node(defaults[h], defaults[h]));
+        }
+        SparseMerkleTree{leaf_hasher, node_hasher, defaults, nodes: BTreeMap::new(), values: BTreeMap::new()}
+    }
+
+    pub fn depth(&self) -> usize
+    {
+        self.defaults.len() - 1
+    }
+
+    /// The hash of an empty subtree of the given height.
+    pub fn default_hash(&self, height: usize) -> F
+    {
+        self.defaults[height]
+    }
+
+    pub fn root(&self) -> F
+    {
+        self.get_node(self.depth(), &F::BigInt::from(0))
+    }
+
+    pub fn get(&self, key: &F) -> Option<F>
+    {
+        self.values.get(&key.into_repr()).cloned()
+    }
+
+    /// Sets the value of key, returning its previous value.
+    pub fn insert(&mut self, key: &F, value: F) -> Option<F>
+    {
+        let index = key.into_repr();
+        let previous = self.values.insert(index, value);
+        let leaf_hash = self.leaf_hasher.hash_leaf(&[value]);
+        self.set_leaf(index, leaf_hash);
+        previous
+    }
+
+    /// Removes key, returning its value.
+    pub fn delete(&mut self, key: &F) -> Option<F>
+    {
+        let index = key.into_repr();
+        let previous = self.values.remove(&index);
+        if previous.is_some()
+        {
+            self.set_leaf(index, self.defaults[0]);
+        }
+        previous
+    }
+
+    /// Returns the path of key, which proves membership if key is present, and non-membership otherwise.
+    pub fn prove(&self, key: &F) -> MerklePath<F>
+    {
+        let path = key_to_path(key);
+        let leaf_index = key.into_repr();
+        // auth_path[0] is a child of the root, so the heights run down from depth - 1 to the leaves
+        let auth_path = (0..self.depth()).rev()
+            .map(|height| self.get_node(height, &sibling_index(&node_index(&leaf_index, height))))
+            .collect();
+        MerklePath{path, auth_path}
+    }
+
+    pub fn verify_membership(&self, root: F, key: &F, value: F, proof: &MerklePath<F>) -> bool
+    {
+        proof.path == key_to_path(key) && proof.verify(&self.leaf_hasher, &self.node_hasher, root, &[value])
+    }
+
+    pub fn verify_non_membership(&self, root: F, key: &F, proof: &MerklePath<F>) -> bool
+    {
+        if proof.path != key_to_path(key)
+        {
+            return false;
+        }
+        match proof.compute_root_from_leaf_hash(&self.node_hasher, self.defaults[0]) {
+            Ok(computed_root) => computed_root == root,
+            Err(_) => false,
+        }
+    }
+
+    fn get_node(&self, height: usize, index: &F::BigInt) -> F
+    {
+        match self.nodes.get(&(height, *index)) {
+            Some(node) => *node,
+            None => self.defaults[height],
+        }
+    }
+
+    fn set_node(&mut self, height: usize, index: F::BigInt, node: F)
+    {
+        if node == self.defaults[height]
+        {
+            self.nodes.remove(&(height, index));
+        }
+        else
+        {
+            self.nodes.insert((height, index), node);
+        }
+    }
+
+    // Sets the leaf at index, and rehashes its ancestors.
+    fn set_leaf(&mut self, mut index: F::BigInt, leaf_hash: F)
+    {
+        self.set_node(0, index, leaf_hash);
+        for height in 1..=self.depth()
+        {
+            let sibling = sibling_index(&index);
+            let (left, right) = if index.is_odd() { (sibling, index) } else { (index, sibling) };
+            let parent = self.node_hasher.hash_node(self.get_node(height - 1, &left), self.get_node(height - 1, &right));
+            index.div2();
+            self.set_node(height, index, parent);
+        }
+    }
+}
+
+// The index in its level of the node at the given height above the leaf with the given index.
+fn node_index<B: BigInteger>(leaf_index: &B, height: usize) -> B
+{
+    let mut index = *leaf_index;
+    index.divn(height as u32);
+    index
+}
+
+// Flips the last bit of index.
+fn sibling_index<B: BigInteger>(index: &B) -> B
+{
+    let mut sibling = *index;
+    if sibling.is_odd()
+    {
+        sibling.sub_noborrow(&B::from(1));
+    }
+    else
+    {
+        sibling.add_nocarry(&B::from(1));
+    }
+    sibling
+}
+
+/// Verifies SparseMerkleTree proofs in-circuit.
+/// The key is decomposed with `to_bits_strict`, so each key has exactly one path.
+pub struct SparseMerklePathGadget<LH, NH>
+{
+    pub leafHasher: LH,
+    pub nodeHasher: NH,
+}
+
+impl<LH, NH> SparseMerklePathGadget<LH, NH>
+{
+    /// Enforces that key holds value in the tree with the given root.
+    pub fn verify_membership<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        key: &FG,
+        value: &FG,
+        root: FG,
+        auth_path: &[FG]) -> Result<(), Error>
+    where
+        LH: LeafHasherGadget<F, FG>, NH: NodeHasherGadget<F, FG>
+    {
+        let path = key.to_bits_strict(&mut cs.ns(|| "key bits"))?;
+        let leafHash = self.leafHasher.hash_leaf(&mut cs.ns(|| "leafhash"), &[value.clone()])?;
+        let path_gadget = MerklePathGadget{leafHasher: (), nodeHasher: &self.nodeHasher};
+        let curHash = path_gadget.compute_root_from_leaf_hash(&mut cs.ns(|| "compute root"), &path, auth_path, leafHash)?;
+        curHash.enforce_equal(&mut cs.ns(|| "root check"), &root)?;
+        Ok(())
+    }
+
+    /// Enforces that key is absent from the tree with the given root, i.e. that its leaf is empty.
+    pub fn verify_non_membership<F: PrimeField, FG: FieldGadget<F, F>, CS: ConstraintSystem<F>>(&self,
+        mut cs: CS,
+        key: &FG,
+        root: FG,
+        auth_path: &[FG]) -> Result<(), Error>
+    where
+        NH: NodeHasherGadget<F, FG>
+    {
+        let path = key.to_bits_strict(&mut cs.ns(|| "key bits"))?;
+        let emptyLeaf = FG::zero(&mut cs.ns(|| "empty leaf"))?;
+        let path_gadget = MerklePathGadget{leafHasher: (), nodeHasher: &self.nodeHasher};
+        let curHash = path_gadget.compute_root_from_leaf_hash(&mut cs.ns(|| "compute root"), &path, auth_path, emptyLeaf)?;
+        curHash.enforce_equal(&mut cs.ns(|| "root check"), &root)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use r1cs_std::{prelude::*, test_constraint_system::TestConstraintSystem};
+    use r1cs_core::ConstraintSystem;
+    use algebra::prelude::*;
+    use crate::merkle_tree::{*, sparse::*};
+    use crate::hashing::{sponge::*, leafhash::*, two_to_one_hash::*, dummy_permutation::*};
+    use crate::alt_bn128::fr_gadget::FrGadget;
+    use crate::alt_bn128::fr::Fr;
+    use crate::Error;
+
+    fn new_tree() -> Result<SparseMerkleTree<Fr, LeafHash<Fr, MixingDummyPermutation<Fr>>, TwoToOneHash<Fr, MixingDummyPermutation<Fr>>>, Error> {
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge = AlgebraicSponge::<Fr, MixingDummyPermutation<Fr>>::new(2, 1, permutation);
+        Ok(SparseMerkleTree::new(LeafHash::new(sponge.clone())?, TwoToOneHash::new(sponge)?))
+    }
+
+    #[test]
+    fn sparse_mt_test() -> Result<(), Error> {
+        let mut tree = new_tree()?;
+        let empty_root = tree.root();
+        assert_eq!(empty_root, tree.default_hash(tree.depth()));
+
+        let key_1 = Fr::from(12345u32);
+        let key_2 = -Fr::one();
+        assert_eq!(tree.insert(&key_1, Fr::from(1u32)), None);
+        assert_eq!(tree.insert(&key_2, Fr::from(2u32)), None);
+        assert_eq!(tree.get(&key_1), Some(Fr::from(1u32)));
+        assert_eq!(tree.insert(&key_1, Fr::from(3u32)), Some(Fr::from(1u32)));
+        let root = tree.root();
+
+        let proof = tree.prove(&key_1);
+        assert!(tree.verify_membership(root, &key_1, Fr::from(3u32), &proof));
+        assert!(!tree.verify_membership(root, &key_1, Fr::from(1u32), &proof));
+        assert!(!tree.verify_non_membership(root, &key_1, &proof));
+        let absent = Fr::from(12344u32);
+        let proof = tree.prove(&absent);
+        assert!(tree.verify_non_membership(root, &absent, &proof));
+        assert!(!tree.verify_membership(root, &absent, Fr::zero(), &proof));
+
+        // Deleting every key restores the empty tree, without any stored nodes left over
+        assert_eq!(tree.delete(&key_1), Some(Fr::from(3u32)));
+        assert_eq!(tree.delete(&key_1), None);
+        assert_eq!(tree.delete(&key_2), Some(Fr::from(2u32)));
+        assert_eq!(tree.root(), empty_root);
+        assert!(tree.nodes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_mt_gadget_test() -> Result<(), Error> {
+        let mut tree = new_tree()?;
+        let key = Fr::from(777u32);
+        let value = Fr::from(5u32);
+        let absent = Fr::from(778u32);
+        tree.insert(&key, value);
+        tree.insert(&Fr::from(1u32), Fr::from(6u32));
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let permutation = MixingDummyPermutation{seed: Fr::from(3u32)};
+        let sponge_gadget = AlgebraicSpongeGadget::<Fr, FrGadget, MixingDummyPermutation<Fr>>::
+            new(&mut cs.ns(|| "sponge"), 2, 1, permutation)?;
+        let SMT_gadget = SparseMerklePathGadget{
+            leafHasher: LeafHashGadget::new(&mut cs.ns(|| "leaf hasher"), sponge_gadget.clone())?,
+            nodeHasher: TwoToOneHashGadget::new(&mut cs.ns(|| "node hasher"), sponge_gadget)?,
+        };
+        let root = FrGadget::alloc(&mut cs.ns(|| "root"), || Ok(tree.root()))?;
+
+        let proof = tree.prove(&key);
+        let mut auth_path = Vec::new();
+        for i in 0..proof.auth_path.len() {
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let key_gadget = FrGadget::alloc(&mut cs.ns(|| "key"), || Ok(key))?;
+        let value_gadget = FrGadget::alloc(&mut cs.ns(|| "value"), || Ok(value))?;
+        SMT_gadget.verify_membership(&mut cs.ns(|| "membership"), &key_gadget, &value_gadget, root.clone(), &auth_path)?;
+
+        let proof = tree.prove(&absent);
+        let mut auth_path = Vec::new();
+        for i in 0..proof.auth_path.len() {
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("absent auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        let absent_gadget = FrGadget::alloc(&mut cs.ns(|| "absent key"), || Ok(absent))?;
+        SMT_gadget.verify_non_membership(&mut cs.ns(|| "non membership"), &absent_gadget, root.clone(), &auth_path)?;
+        assert!(cs.is_satisfied());
+
+        // The key holding a value can't be shown absent
+        let proof = tree.prove(&key);
+        let mut auth_path = Vec::new();
+        for i in 0..proof.auth_path.len() {
+            auth_path.push(FrGadget::alloc(&mut cs.ns(|| format!("present auth path {:?}", i)), || Ok(proof.auth_path[i]))?);
+        }
+        SMT_gadget.verify_non_membership(&mut cs.ns(|| "bad non membership"), &key_gadget, root, &auth_path)?;
+        assert!(!cs.is_satisfied());
+        Ok(())
+    }
+}